@@ -1,6 +1,26 @@
 use glib_build_tools::compile_resources;
+use std::fs;
+
+/// Finds the Servo revision the crate is built against, which the widget
+/// and the runner compare when connecting.
+fn engine_revision() -> String {
+    let manifest = fs::read_to_string("Cargo.toml").expect("Failed to read Cargo.toml");
+    manifest
+        .lines()
+        .find(|line| line.trim_start().starts_with("libservo"))
+        .and_then(|line| line.split("rev = \"").nth(1))
+        .and_then(|rest| rest.split('"').next())
+        .unwrap_or("unknown")
+        .to_string()
+}
 
 fn main() {
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!(
+        "cargo:rustc-env=SERVO_GTK_ENGINE_REVISION={}",
+        engine_revision()
+    );
+
     compile_resources(
         &["resources"],
         "resources/gresource.xml",
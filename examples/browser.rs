@@ -63,6 +63,7 @@ fn main() -> glib::ExitCode {
         let web_view = WebView::new();
         web_view.set_hexpand(true);
         web_view.set_vexpand(true);
+        web_view.set_restart_on_crash(true);
 
         let web_view_clone = web_view.clone();
         url_entry.connect_activate(move |entry| {
@@ -85,6 +86,26 @@ fn main() -> glib::ExitCode {
             web_view_clone.go_forward();
         });
 
+        web_view
+            .bind_property("can-go-back", &back_button, "sensitive")
+            .sync_create()
+            .build();
+        web_view
+            .bind_property("can-go-forward", &forward_button, "sensitive")
+            .sync_create()
+            .build();
+        web_view
+            .bind_property("title", &window, "title")
+            .sync_create()
+            .build();
+
+        let url_entry_clone = url_entry.clone();
+        web_view.connect_uri_notify(move |web_view| {
+            if let Some(uri) = web_view.uri() {
+                url_entry_clone.set_text(&uri);
+            }
+        });
+
         hbox.append(&back_button);
         hbox.append(&forward_button);
         hbox.append(&reload_button);
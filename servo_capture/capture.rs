@@ -0,0 +1,239 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Renders a page with the servo-runner and writes it to a PNG file.
+
+use glib::info;
+use gtk::glib;
+use servo_gtk::{HeadlessView, JsValue, SnapshotOptions, SnapshotRegion};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+const G_LOG_DOMAIN: &str = "ServoGtkCapture";
+
+const LOGGER: glib::GlibLogger = glib::GlibLogger::new(
+    glib::GlibLoggerFormat::Plain,
+    glib::GlibLoggerDomain::CrateTarget,
+);
+
+/// How often `--wait-for` checks for its selector.
+const SELECTOR_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const USAGE: &str = "\
+Usage: servo-gtk-capture [OPTIONS] URL
+
+Options:
+  -o, --output PATH       Where to write the PNG image [screenshot.png]
+      --width PIXELS      Width of the viewport [1280]
+      --height PIXELS     Height of the viewport [800]
+      --full-page         Capture the whole document instead of the viewport
+      --scale FACTOR      Scale the captured image by FACTOR [1]
+      --wait-for SELECTOR Wait until an element matches SELECTOR
+      --delay MS          Wait MS milliseconds before capturing
+      --css CSS           Add CSS to the page, can be repeated
+      --css-file PATH     Add the CSS in PATH to the page, can be repeated
+      --timeout SECONDS   Give up loading and waiting after SECONDS [30]
+  -h, --help              Show this help";
+
+struct Options {
+    url: String,
+    output: PathBuf,
+    width: u32,
+    height: u32,
+    full_page: bool,
+    scale: f64,
+    wait_for: Option<String>,
+    delay: Duration,
+    css: Vec<String>,
+    timeout: Duration,
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{option} needs a value"))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {option}: {value}"))
+}
+
+/// Returns `None` when only the help was asked for.
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut url = None;
+    let mut options = Options {
+        url: String::new(),
+        output: PathBuf::from("screenshot.png"),
+        width: 1280,
+        height: 800,
+        full_page: false,
+        scale: 1.0,
+        wait_for: None,
+        delay: Duration::ZERO,
+        css: Vec::new(),
+        timeout: Duration::from_secs(30),
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => options.output = parse_value(&arg, args.next())?,
+            "--width" => options.width = parse_value(&arg, args.next())?,
+            "--height" => options.height = parse_value(&arg, args.next())?,
+            "--full-page" => options.full_page = true,
+            "--scale" => options.scale = parse_value(&arg, args.next())?,
+            "--wait-for" => options.wait_for = Some(parse_value(&arg, args.next())?),
+            "--delay" => options.delay = Duration::from_millis(parse_value(&arg, args.next())?),
+            "--css" => options.css.push(parse_value(&arg, args.next())?),
+            "--css-file" => {
+                let path: PathBuf = parse_value(&arg, args.next())?;
+                let css = fs::read_to_string(&path)
+                    .map_err(|err| format!("Failed to read {}: {err}", path.display()))?;
+                options.css.push(css);
+            }
+            "--timeout" => {
+                options.timeout = Duration::from_secs(parse_value(&arg, args.next())?);
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {arg}")),
+            _ if url.is_none() => url = Some(arg),
+            _ => return Err(format!("Unexpected argument {arg}")),
+        }
+    }
+
+    options.url = url.ok_or("Missing URL")?;
+    if options.width == 0 || options.height == 0 {
+        return Err("The viewport can't be empty".to_string());
+    }
+    if options.scale.is_nan() || options.scale <= 0.0 {
+        return Err("The scale has to be positive".to_string());
+    }
+    if options
+        .output
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"))
+    {
+        // Servo can't print pages yet
+        return Err("Only PNG output is supported".to_string());
+    }
+    Ok(Some(options))
+}
+
+/// Quotes `value` as a JavaScript string literal.
+fn js_string(value: &str) -> String {
+    let escaped: String = value
+        .encode_utf16()
+        .map(|unit| format!("\\u{unit:04x}"))
+        .collect();
+    format!("\"{escaped}\"")
+}
+
+async fn with_timeout<T>(
+    timeout: Duration,
+    what: &str,
+    future: impl Future<Output = Result<T, glib::Error>>,
+) -> Result<T, String> {
+    glib::future_with_timeout(timeout, future)
+        .await
+        .map_err(|_| format!("Timed out {what}"))?
+        .map_err(|err| format!("Failed {what}: {err}"))
+}
+
+async fn wait_for_selector(
+    view: &HeadlessView,
+    selector: &str,
+    timeout: Duration,
+) -> Result<(), String> {
+    let script = format!("document.querySelector({}) !== null", js_string(selector));
+    let deadline = Instant::now() + timeout;
+    let what = format!("looking for {selector}");
+    loop {
+        // Every check only gets the time left, a hung page can't stall us
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let found = with_timeout(remaining, &what, view.evaluate_javascript(&script)).await?;
+        if matches!(found, JsValue::Boolean(true)) {
+            return Ok(());
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(format!("Timed out waiting for {selector}"));
+        }
+        glib::timeout_future(SELECTOR_POLL_INTERVAL.min(remaining)).await;
+    }
+}
+
+async fn capture(options: Options) -> Result<(), String> {
+    let view = HeadlessView::new(None, options.width, options.height)
+        .map_err(|err| format!("Failed to start the runner: {err}"))?;
+
+    info!("Loading {}", options.url);
+    view.load_url(&options.url);
+    with_timeout(options.timeout, "loading the page", view.wait_for_load()).await?;
+
+    for css in &options.css {
+        let script = format!(
+            "document.head.appendChild(Object.assign(document.createElement(\"style\"), \
+             {{ textContent: {} }}))",
+            js_string(css)
+        );
+        with_timeout(
+            options.timeout,
+            "injecting CSS",
+            view.evaluate_javascript(&script),
+        )
+        .await?;
+    }
+
+    if let Some(selector) = &options.wait_for {
+        wait_for_selector(&view, selector, options.timeout).await?;
+    }
+
+    if !options.delay.is_zero() {
+        glib::timeout_future(options.delay).await;
+    }
+
+    let region = if options.full_page {
+        SnapshotRegion::FullDocument
+    } else {
+        SnapshotRegion::Visible
+    };
+    let snapshot_options = SnapshotOptions {
+        scale: options.scale,
+    };
+    let png = with_timeout(
+        options.timeout,
+        "capturing the page",
+        view.screenshot(region, snapshot_options),
+    )
+    .await?;
+
+    fs::write(&options.output, &png)
+        .map_err(|err| format!("Failed to write {}: {err}", options.output.display()))?;
+    info!("Wrote {}", options.output.display());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    log::set_logger(&LOGGER).expect("logger already set");
+    log::set_max_level(log::LevelFilter::Info);
+
+    let options = match parse_options(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match glib::MainContext::default().block_on(capture(options)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,207 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::web_view::WebView;
+use glib::subclass::Signal;
+use gtk::gio;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::fs::OpenOptions;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+mod imp {
+    use super::*;
+
+    #[derive(Default, glib::Properties)]
+    #[properties(wrapper_type = super::Download)]
+    pub struct Download {
+        pub download_id: Cell<u64>,
+        pub web_view: glib::WeakRef<WebView>,
+        pub completed: Cell<bool>,
+        /// The URI being downloaded
+        #[property(get)]
+        pub uri: RefCell<String>,
+        /// The path the download is written to, once decided
+        #[property(get, nullable)]
+        pub destination: RefCell<Option<String>>,
+        /// The MIME type reported by the server
+        #[property(get, nullable)]
+        pub mime_type: RefCell<Option<String>>,
+        /// How much has been received so far
+        #[property(get)]
+        pub received_bytes: Cell<u64>,
+        /// The size of the download, or 0 when unknown
+        #[property(get)]
+        pub total_bytes: Cell<u64>,
+        /// How far the download is, between 0 and 1
+        #[property(get, minimum = 0.0, maximum = 1.0)]
+        pub progress: Cell<f64>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for Download {
+        const NAME: &'static str = "Download";
+        type Type = super::Download;
+        type ParentType = glib::Object;
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for Download {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            SIGNALS.get_or_init(|| {
+                vec![
+                    Signal::builder("finished").build(),
+                    Signal::builder("failed")
+                        .param_types([glib::Error::static_type()])
+                        .build(),
+                ]
+            })
+        }
+    }
+}
+
+glib::wrapper! {
+    /// A file being downloaded by a [`WebView`].
+    ///
+    /// Emits `finished` once the whole file has been written to its
+    /// destination, or `failed` otherwise, including when it gets
+    /// cancelled.
+    pub struct Download(ObjectSubclass<imp::Download>);
+}
+
+/// Creates a file for `filename` in `directory`, appending a number to the
+/// name until it doesn't clash with an existing file.
+fn unique_destination(directory: &Path, filename: &str) -> io::Result<PathBuf> {
+    let (stem, extension) = match filename.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{extension}")),
+        _ => (filename, String::new()),
+    };
+    for index in 0.. {
+        let path = if index == 0 {
+            directory.join(filename)
+        } else {
+            directory.join(format!("{stem} ({index}){extension}"))
+        };
+        // Creating the file claims the name, the runner then writes to it
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => return Ok(path),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    unreachable!("Some file name is free")
+}
+
+impl Download {
+    pub(crate) fn new(download_id: u64, web_view: &WebView, uri: &str) -> Self {
+        let download: Self = glib::Object::builder().build();
+        let imp = download.imp();
+        imp.download_id.set(download_id);
+        imp.web_view.set(Some(web_view));
+        imp.uri.replace(uri.to_string());
+        download
+    }
+
+    pub fn web_view(&self) -> Option<WebView> {
+        self.imp().web_view.upgrade()
+    }
+
+    /// Writes the download to `path`.
+    ///
+    /// Meant for `decide-destination` handlers, it has no effect once a
+    /// destination has been set.
+    pub fn set_destination(&self, path: impl AsRef<Path>) {
+        let imp = self.imp();
+        if imp.completed.get() || imp.destination.borrow().is_some() {
+            return;
+        }
+
+        let path = path.as_ref().to_string_lossy().into_owned();
+        if let Some(web_view) = imp.web_view.upgrade() {
+            web_view.set_download_destination(imp.download_id.get(), &path);
+        }
+        imp.destination.replace(Some(path));
+        self.notify_destination();
+    }
+
+    /// Stops the download and removes what was written of it.
+    pub fn cancel(&self) {
+        self.abort(glib::Error::new(
+            gio::IOErrorEnum::Cancelled,
+            "The download was cancelled",
+        ));
+    }
+
+    fn abort(&self, error: glib::Error) {
+        let imp = self.imp();
+        if imp.completed.get() {
+            return;
+        }
+        if let Some(web_view) = imp.web_view.upgrade() {
+            web_view.cancel_download(imp.download_id.get());
+        }
+        self.fail(error);
+    }
+
+    /// Picks a free name in the user's download directory.
+    pub(crate) fn set_default_destination(&self, suggested_filename: &str) {
+        let directory =
+            glib::user_special_dir(glib::UserDirectory::Downloads).unwrap_or_else(glib::home_dir);
+        match unique_destination(&directory, suggested_filename) {
+            Ok(path) => self.set_destination(path),
+            Err(err) => self.abort(glib::Error::new(
+                gio::IOErrorEnum::Failed,
+                &format!(
+                    "Failed to create the download in {}: {err}",
+                    directory.display()
+                ),
+            )),
+        }
+    }
+
+    pub(crate) fn set_response(&self, mime_type: String, total_bytes: u64) {
+        let imp = self.imp();
+        imp.mime_type
+            .replace((!mime_type.is_empty()).then_some(mime_type));
+        imp.total_bytes.set(total_bytes);
+        self.notify_mime_type();
+        self.notify_total_bytes();
+    }
+
+    pub(crate) fn set_received_bytes(&self, received_bytes: u64) {
+        let imp = self.imp();
+        imp.received_bytes.set(received_bytes);
+        self.notify_received_bytes();
+
+        let total_bytes = imp.total_bytes.get();
+        if total_bytes > 0 {
+            imp.progress
+                .set((received_bytes as f64 / total_bytes as f64).min(1.0));
+            self.notify_progress();
+        }
+    }
+
+    pub(crate) fn finish(&self) {
+        let imp = self.imp();
+        if imp.completed.replace(true) {
+            return;
+        }
+        imp.progress.set(1.0);
+        self.notify_progress();
+        self.emit_by_name::<()>("finished", &[]);
+    }
+
+    pub(crate) fn fail(&self, error: glib::Error) {
+        let imp = self.imp();
+        if imp.completed.replace(true) {
+            return;
+        }
+        self.emit_by_name::<()>("failed", &[&error]);
+    }
+}
@@ -0,0 +1,168 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::web_view::WebView;
+use gtk::gio;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
+
+mod imp {
+    use super::*;
+
+    #[derive(Default, glib::Properties)]
+    #[properties(wrapper_type = super::FileChooserRequest)]
+    pub struct FileChooserRequest {
+        pub request_id: Cell<u64>,
+        pub web_view: glib::WeakRef<WebView>,
+        pub finished: Cell<bool>,
+        /// The file extensions and MIME types the page accepts, empty when
+        /// any file will do
+        #[property(get)]
+        pub accept: RefCell<Vec<String>>,
+        /// Whether several files can be selected
+        #[property(get)]
+        pub select_multiple: Cell<bool>,
+        /// The directory to start browsing from
+        #[property(get, nullable)]
+        pub directory: RefCell<Option<String>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for FileChooserRequest {
+        const NAME: &'static str = "FileChooserRequest";
+        type Type = super::FileChooserRequest;
+        type ParentType = glib::Object;
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for FileChooserRequest {
+        fn dispose(&self) {
+            // The file input waits until it gets an answer
+            self.obj().cancel();
+        }
+    }
+}
+
+glib::wrapper! {
+    /// A file input asking for files, passed to the `run-file-chooser`
+    /// signal.
+    ///
+    /// The request is answered with `select_files` or `cancel`. A request
+    /// that gets dropped without an answer is cancelled.
+    pub struct FileChooserRequest(ObjectSubclass<imp::FileChooserRequest>);
+}
+
+impl FileChooserRequest {
+    pub(crate) fn new(
+        request_id: u64,
+        web_view: &WebView,
+        accept: Vec<String>,
+        select_multiple: bool,
+        directory: String,
+    ) -> Self {
+        let request: Self = glib::Object::builder().build();
+        let imp = request.imp();
+        imp.request_id.set(request_id);
+        imp.web_view.set(Some(web_view));
+        imp.accept.replace(accept);
+        imp.select_multiple.set(select_multiple);
+        imp.directory
+            .replace((!directory.is_empty()).then_some(directory));
+        request
+    }
+
+    fn finish(&self, paths: &[PathBuf]) {
+        let imp = self.imp();
+        if imp.finished.replace(true) {
+            return;
+        }
+        if let Some(web_view) = imp.web_view.upgrade() {
+            web_view.finish_file_chooser_request(imp.request_id.get(), paths);
+        }
+    }
+
+    /// Hands `paths` to the page. Only the first one is used unless
+    /// `select-multiple` is set.
+    pub fn select_files(&self, paths: &[PathBuf]) {
+        if self.select_multiple() {
+            self.finish(paths);
+        } else {
+            self.finish(&paths[..paths.len().min(1)]);
+        }
+    }
+
+    /// Leaves the file input unchanged.
+    pub fn cancel(&self) {
+        self.finish(&[]);
+    }
+
+    fn file_filters(&self) -> Option<(gio::ListStore, gtk::FileFilter)> {
+        let accept = self.accept();
+        if accept.is_empty() {
+            return None;
+        }
+
+        let accepted_filter = gtk::FileFilter::new();
+        accepted_filter.set_name(Some("Supported files"));
+        for pattern in &accept {
+            if pattern.contains('/') {
+                accepted_filter.add_mime_type(pattern);
+            } else {
+                accepted_filter.add_suffix(pattern.trim_start_matches('.'));
+            }
+        }
+
+        let all_filter = gtk::FileFilter::new();
+        all_filter.set_name(Some("All files"));
+        all_filter.add_pattern("*");
+
+        let filters = gio::ListStore::new::<gtk::FileFilter>();
+        filters.append(&accepted_filter);
+        filters.append(&all_filter);
+        Some((filters, accepted_filter))
+    }
+
+    /// Shows a `gtk::FileDialog`, used when no `run-file-chooser` handler
+    /// takes care of the request.
+    pub(crate) fn run_default(&self, parent: Option<gtk::Window>) {
+        let file_dialog = gtk::FileDialog::builder().modal(true).build();
+        if self.select_multiple() {
+            file_dialog.set_title("Select Files");
+        } else {
+            file_dialog.set_title("Select a File");
+        }
+        if let Some((filters, default_filter)) = self.file_filters() {
+            file_dialog.set_filters(Some(&filters));
+            file_dialog.set_default_filter(Some(&default_filter));
+        }
+        if let Some(directory) = self.directory() {
+            file_dialog.set_initial_folder(Some(&gio::File::for_path(directory)));
+        }
+
+        let request = self.clone();
+        glib::spawn_future_local(async move {
+            let files = if request.select_multiple() {
+                file_dialog
+                    .open_multiple_future(parent.as_ref())
+                    .await
+                    .map(|files| files.iter::<gio::File>().flatten().collect())
+            } else {
+                file_dialog
+                    .open_future(parent.as_ref())
+                    .await
+                    .map(|file| vec![file])
+            };
+            match files {
+                Ok(files) => {
+                    let paths: Vec<PathBuf> = files.iter().filter_map(|file| file.path()).collect();
+                    request.select_files(&paths);
+                }
+                Err(_) => request.cancel(),
+            }
+        });
+    }
+}
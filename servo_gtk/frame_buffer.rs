@@ -0,0 +1,295 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use memmap2::Mmap;
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::ops::Range;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::FileExt;
+use std::ptr;
+use std::sync::Arc;
+
+/// File descriptor number of the socket the runner receives frame buffers
+/// from.
+pub const FRAME_SOCKET_FD: RawFd = 3;
+
+/// Number of frame slots in the ring buffer.
+pub const FRAME_BUFFER_SLOTS: u32 = 3;
+
+/// Size in pixels views render at until the widget resizes them.
+pub const INITIAL_FRAME_SIZE: (u32, u32) = (800, 600);
+
+/// Shared-memory ring buffer used to transfer rendered frames from the
+/// runner to the widget without going through the IPC pipe.
+///
+/// The buffer is split in `FRAME_BUFFER_SLOTS` slots, each the size of a
+/// frame of the view when the buffer was made. The widget sends a buffer
+/// of the right size along with resizes outgrowing it, or leaving most of
+/// it unused, and tells buffers apart by their id.
+///
+/// The widget wraps slots in textures without copying them, so a slot is
+/// held from the moment a frame is written to it until the widget releases
+/// it, and isn't written to meanwhile.
+pub struct FrameBuffer {
+    id: u32,
+    file: File,
+    /// Size in bytes of each slot
+    slot_size: u64,
+    next_slot: Cell<u32>,
+    /// Bit mask of the slots the widget still uses
+    held_slots: Cell<u32>,
+    mapping: RefCell<Option<Arc<Mmap>>>,
+}
+
+/// The bytes of one slot, released once the texture using them is gone.
+struct FrameSlot {
+    mapping: Arc<Mmap>,
+    range: Range<usize>,
+    release: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl AsRef<[u8]> for FrameSlot {
+    fn as_ref(&self) -> &[u8] {
+        &self.mapping[self.range.clone()]
+    }
+}
+
+impl Drop for FrameSlot {
+    fn drop(&mut self) {
+        if let Some(release) = self.release.take() {
+            release();
+        }
+    }
+}
+
+/// Size in bytes of a slot holding frames of `width` by `height` pixels.
+pub fn slot_size(width: u32, height: u32) -> io::Result<u64> {
+    (width as u64 * 4)
+        .checked_mul(height as u64)
+        .filter(|size| size.checked_mul(FRAME_BUFFER_SLOTS as u64).is_some())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("frames of {width}x{height} pixels are too large to share"),
+            )
+        })
+}
+
+impl FrameBuffer {
+    /// Creates a new anonymous memfd backed frame buffer `id`, for frames
+    /// of `width` by `height` pixels.
+    pub fn new(id: u32, width: u32, height: u32) -> io::Result<Self> {
+        let slot_size = slot_size(width, height)?;
+        let fd = unsafe { libc::memfd_create(c"servo-gtk-frames".as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let file = File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+        file.set_len(slot_size * FRAME_BUFFER_SLOTS as u64)?;
+        Ok(Self::from_file(id, file, slot_size))
+    }
+
+    fn from_file(id: u32, file: File, slot_size: u64) -> Self {
+        Self {
+            id,
+            file,
+            slot_size,
+            next_slot: Cell::new(0),
+            held_slots: Cell::new(0),
+            mapping: RefCell::new(None),
+        }
+    }
+
+    /// The id frames written to the buffer are sent with.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Whether the slots of the buffer fit frames of `width` by `height`
+    /// pixels without wasting most of their memory.
+    pub fn fits(&self, width: u32, height: u32) -> bool {
+        slot_size(width, height)
+            .is_ok_and(|size| size <= self.slot_size && size >= self.slot_size / 4)
+    }
+
+    /// Creates the connected pair of sockets frame buffers are passed
+    /// through, one end for each process.
+    pub fn socket_pair() -> io::Result<(OwnedFd, OwnedFd)> {
+        let mut fds = [0; 2];
+        let result = unsafe {
+            libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_STREAM | libc::SOCK_CLOEXEC,
+                0,
+                fds.as_mut_ptr(),
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+    }
+
+    /// Passes the file descriptor of the buffer over `socket`.
+    pub fn send(&self, socket: BorrowedFd) -> io::Result<()> {
+        let mut data = [0u8; 1];
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr().cast(),
+            iov_len: data.len(),
+        };
+        // Room for one file descriptor, aligned for the control header
+        let mut control = [0u64; 4];
+
+        let mut message: libc::msghdr = unsafe { mem::zeroed() };
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        message.msg_control = control.as_mut_ptr().cast();
+        message.msg_controllen = unsafe { libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) } as _;
+
+        unsafe {
+            let header = libc::CMSG_FIRSTHDR(&message);
+            (*header).cmsg_level = libc::SOL_SOCKET;
+            (*header).cmsg_type = libc::SCM_RIGHTS;
+            (*header).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+            ptr::write_unaligned(
+                libc::CMSG_DATA(header).cast::<RawFd>(),
+                self.file.as_raw_fd(),
+            );
+        }
+
+        if unsafe { libc::sendmsg(socket.as_raw_fd(), &message, libc::MSG_NOSIGNAL) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Takes the next frame buffer sent over `socket`, blocking until there
+    /// is one, as buffer `id`.
+    pub fn receive(socket: BorrowedFd, id: u32) -> io::Result<Self> {
+        let mut data = [0u8; 1];
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr().cast(),
+            iov_len: data.len(),
+        };
+        let mut control = [0u64; 4];
+
+        let mut message: libc::msghdr = unsafe { mem::zeroed() };
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        message.msg_control = control.as_mut_ptr().cast();
+        message.msg_controllen = mem::size_of_val(&control) as _;
+
+        let received =
+            unsafe { libc::recvmsg(socket.as_raw_fd(), &mut message, libc::MSG_CMSG_CLOEXEC) };
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if received == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        unsafe {
+            let header = libc::CMSG_FIRSTHDR(&message);
+            if header.is_null()
+                || (*header).cmsg_level != libc::SOL_SOCKET
+                || (*header).cmsg_type != libc::SCM_RIGHTS
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "no frame buffer in the message",
+                ));
+            }
+            let fd = ptr::read_unaligned(libc::CMSG_DATA(header).cast::<RawFd>());
+            let file = File::from(OwnedFd::from_raw_fd(fd));
+            let slot_size = file.metadata()?.len() / FRAME_BUFFER_SLOTS as u64;
+            Ok(Self::from_file(id, file, slot_size))
+        }
+    }
+
+    fn slot_offset(&self, buffer_index: u32) -> u64 {
+        buffer_index as u64 * self.slot_size
+    }
+
+    /// Copies a frame into the next slot the widget doesn't hold, and
+    /// returns its index. Returns `None` when the widget holds them all.
+    pub fn write_frame(&self, data: &[u8], stride: u32, height: u32) -> io::Result<Option<u32>> {
+        let frame_size = stride as u64 * height as u64;
+        if frame_size > self.slot_size || data.len() as u64 > frame_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "frame of {frame_size} bytes larger than the {} byte slots of the shared buffer",
+                    self.slot_size
+                ),
+            ));
+        }
+
+        let held_slots = self.held_slots.get();
+        let Some(buffer_index) = (0..FRAME_BUFFER_SLOTS)
+            .map(|slot| (self.next_slot.get() + slot) % FRAME_BUFFER_SLOTS)
+            .find(|slot| held_slots & (1 << slot) == 0)
+        else {
+            return Ok(None);
+        };
+
+        self.file
+            .write_all_at(data, self.slot_offset(buffer_index))?;
+        self.held_slots.set(held_slots | (1 << buffer_index));
+        self.next_slot.set((buffer_index + 1) % FRAME_BUFFER_SLOTS);
+
+        Ok(Some(buffer_index))
+    }
+
+    /// Makes the slot `buffer_index` available again, once the widget is
+    /// done with it.
+    pub fn release(&self, buffer_index: u32) {
+        if buffer_index < FRAME_BUFFER_SLOTS {
+            self.held_slots
+                .set(self.held_slots.get() & !(1 << buffer_index));
+        }
+    }
+
+    /// Returns the bytes of a frame slot, backed by the shared mapping.
+    /// `release` is called once the returned bytes are freed.
+    pub fn read_frame(
+        &self,
+        buffer_index: u32,
+        stride: u32,
+        height: u32,
+        release: impl FnOnce() + Send + 'static,
+    ) -> io::Result<glib::Bytes> {
+        let frame_size = stride as u64 * height as u64;
+        if buffer_index >= FRAME_BUFFER_SLOTS || frame_size > self.slot_size {
+            release();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "frame outside of the shared buffer",
+            ));
+        }
+
+        let mut mapping = self.mapping.borrow_mut();
+        if mapping.is_none() {
+            let mmap = unsafe { Mmap::map(&self.file) };
+            match mmap {
+                Ok(mmap) => mapping.replace(Arc::new(mmap)),
+                Err(err) => {
+                    release();
+                    return Err(err);
+                }
+            };
+        }
+
+        let offset = self.slot_offset(buffer_index) as usize;
+        Ok(glib::Bytes::from_owned(FrameSlot {
+            mapping: mapping.clone().expect("Frame buffer mapping"),
+            range: offset..offset + frame_size as usize,
+            release: Some(Box::new(release)),
+        }))
+    }
+}
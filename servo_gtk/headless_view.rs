@@ -0,0 +1,432 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::js_value::JsValue;
+use crate::key_tables::KeyEvent;
+use crate::proto_ipc::{DialogType, ScrollPhase, ServoEvent, servo_event};
+use crate::servo_runner::{LogLevel, RunnerProcess, ScrollEvent, ServoRunner, TerminationReason};
+use crate::snapshot::{SnapshotOptions, SnapshotRegion};
+use crate::web_context::WebContext;
+use glib::subclass::Signal;
+use glib::warn;
+use gtk::gdk;
+use gtk::gio;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use keyboard_types::Modifiers;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+const G_LOG_DOMAIN: &str = "ServoGtk";
+
+mod imp {
+    use super::*;
+
+    #[derive(Default, glib::Properties)]
+    #[properties(wrapper_type = super::HeadlessView)]
+    pub struct HeadlessView {
+        pub servo_runner: RefCell<Option<ServoRunner>>,
+        /// Waits for loads and for the loads they replaced to end, 0
+        /// waiting for a load the page started itself
+        pub load_waiters: RefCell<Vec<(u64, async_channel::Sender<Result<(), glib::Error>>)>>,
+        /// The last load asked for, until it ends
+        pub load_id: Cell<u64>,
+        /// Why the runner can't be used, if it turned out to be incompatible
+        pub failure: RefCell<Option<glib::Error>>,
+        /// The title of the current page
+        #[property(get)]
+        pub title: RefCell<Option<String>>,
+        /// The URI of the current page
+        #[property(get)]
+        pub uri: RefCell<Option<String>>,
+        /// Whether a page is being loaded
+        #[property(get)]
+        pub is_loading: Cell<bool>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for HeadlessView {
+        const NAME: &'static str = "HeadlessView";
+        type Type = super::HeadlessView;
+        type ParentType = glib::Object;
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for HeadlessView {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            SIGNALS.get_or_init(|| {
+                vec![
+                    Signal::builder("web-process-terminated")
+                        .param_types([TerminationReason::static_type(), i32::static_type()])
+                        .build(),
+                    Signal::builder("web-process-failed")
+                        .param_types([glib::Error::static_type()])
+                        .build(),
+                ]
+            })
+        }
+    }
+}
+
+glib::wrapper! {
+    /// A page rendered without any widget or display, for tests and batch
+    /// rendering.
+    ///
+    /// Each headless view runs its own runner process, driven by the
+    /// thread-default main context. Anything that would need a user, like
+    /// script dialogs, file inputs or popups, gets dismissed.
+    pub struct HeadlessView(ObjectSubclass<imp::HeadlessView>);
+}
+
+impl HeadlessView {
+    /// Spawns the runner found through `web_context`, or the default
+    /// context, and renders at `width` by `height` pixels.
+    pub fn new(
+        web_context: Option<&WebContext>,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, glib::Error> {
+        let web_context = web_context
+            .cloned()
+            .unwrap_or_else(WebContext::default_context);
+        let process = Rc::new(RunnerProcess::new(&web_context.find_runner()?)?);
+        let event_receiver = process.event_receiver();
+        let termination_receiver = process.termination_receiver();
+        let failure_receiver = process.failure_receiver();
+        let servo_runner = ServoRunner::new(process, 1)?;
+        servo_runner.resize(width, height);
+
+        let view: Self = glib::Object::builder().build();
+        view.imp().servo_runner.replace(Some(servo_runner));
+
+        let view_weak = view.downgrade();
+        glib::spawn_future_local(async move {
+            while let Ok(event) = event_receiver.recv().await {
+                match view_weak.upgrade() {
+                    Some(view) => view.process_servo_event(event),
+                    None => break,
+                }
+            }
+        });
+
+        let view_weak = view.downgrade();
+        glib::spawn_future_local(async move {
+            if let Ok((reason, code)) = termination_receiver.recv().await
+                && let Some(view) = view_weak.upgrade()
+            {
+                view.process_runner_terminated(reason, code);
+            }
+        });
+
+        let view_weak = view.downgrade();
+        glib::spawn_future_local(async move {
+            if let Ok(err) = failure_receiver.recv().await
+                && let Some(view) = view_weak.upgrade()
+            {
+                view.process_runner_failed(err);
+            }
+        });
+
+        Ok(view)
+    }
+
+    fn not_running_error(&self) -> glib::Error {
+        self.imp().failure.borrow().clone().unwrap_or_else(|| {
+            glib::Error::new(
+                gio::IOErrorEnum::NotConnected,
+                "The web process is not running",
+            )
+        })
+    }
+
+    /// Why a pending request won't be answered.
+    fn stopped_error(&self) -> glib::Error {
+        self.imp().failure.borrow().clone().unwrap_or_else(|| {
+            glib::Error::new(gio::IOErrorEnum::BrokenPipe, "The web process terminated")
+        })
+    }
+
+    fn with_runner(&self, f: impl FnOnce(&ServoRunner)) {
+        if let Some(servo) = self.imp().servo_runner.borrow().as_ref() {
+            f(servo);
+        }
+    }
+
+    /// Loading starts once the runner gets to it, but `wait_for_load` has
+    /// to wait for `load_id` from now on.
+    fn start_load(&self, load_id: Option<u64>) {
+        if let Some(load_id) = load_id {
+            self.imp().load_id.set(load_id);
+            self.set_loading(true);
+        }
+    }
+
+    pub fn load_url(&self, url: &str) {
+        let load_id = self
+            .imp()
+            .servo_runner
+            .borrow()
+            .as_ref()
+            .map(|servo| servo.load_url(url));
+        self.start_load(load_id);
+    }
+
+    pub fn load_html(&self, html: &str, base_uri: Option<&str>) {
+        let load_id = self
+            .imp()
+            .servo_runner
+            .borrow()
+            .as_ref()
+            .map(|servo| servo.load_html(html, base_uri.unwrap_or_default()));
+        self.start_load(load_id);
+    }
+
+    pub fn resize(&self, width: u32, height: u32) {
+        self.with_runner(|servo| servo.resize(width, height));
+    }
+
+    /// Waits until the current page is loaded. Fails if the last load
+    /// asked for couldn't start.
+    pub async fn wait_for_load(&self) -> Result<(), glib::Error> {
+        let imp = self.imp();
+        if imp.servo_runner.borrow().is_none() {
+            return Err(self.not_running_error());
+        }
+        if !imp.is_loading.get() {
+            return Ok(());
+        }
+
+        let (sender, receiver) = async_channel::bounded(1);
+        imp.load_waiters
+            .borrow_mut()
+            .push((imp.load_id.get(), sender));
+        receiver.recv().await.map_err(|_| self.stopped_error())?
+    }
+
+    /// Evaluates `script` in the current page and returns its result.
+    pub async fn evaluate_javascript(&self, script: &str) -> Result<JsValue, glib::Error> {
+        let receiver = self
+            .imp()
+            .servo_runner
+            .borrow()
+            .as_ref()
+            .map(|servo| servo.evaluate_javascript(script))
+            .ok_or_else(|| self.not_running_error())?;
+        receiver.recv().await.map_err(|_| self.stopped_error())?
+    }
+
+    /// Captures `region` of the page as PNG data. Fails with
+    /// `InvalidArgument` unless the scale is positive.
+    pub async fn screenshot(
+        &self,
+        region: SnapshotRegion,
+        options: SnapshotOptions,
+    ) -> Result<glib::Bytes, glib::Error> {
+        let receiver = self
+            .imp()
+            .servo_runner
+            .borrow()
+            .as_ref()
+            .map(|servo| servo.snapshot(region, options))
+            .ok_or_else(|| self.not_running_error())?;
+        receiver.recv().await.map_err(|_| self.stopped_error())?
+    }
+
+    pub fn move_pointer(&self, x: f64, y: f64) {
+        self.with_runner(|servo| servo.motion(x, y));
+    }
+
+    /// Clicks `button`, 1 being the primary one, at (`x`, `y`).
+    pub fn click(&self, button: u32, x: f64, y: f64) {
+        self.with_runner(|servo| {
+            servo.motion(x, y);
+            servo.button_press(button, x, y, Modifiers::empty());
+            servo.button_release(button, x, y, Modifiers::empty());
+        });
+    }
+
+    /// Scrolls whatever is under (`x`, `y`) by (`dx`, `dy`) pixels.
+    pub fn scroll(&self, x: f64, y: f64, dx: f64, dy: f64) {
+        self.with_runner(|servo| {
+            servo.scroll(&ScrollEvent {
+                x,
+                y,
+                dx,
+                dy,
+                unit: gdk::ScrollUnit::Surface,
+                phase: ScrollPhase::Discrete,
+                modifiers: Modifiers::empty(),
+            })
+        });
+    }
+
+    /// Presses and releases the key named `key`, using the names of the
+    /// DOM `KeyboardEvent.key` values like "Enter" or "ArrowDown".
+    pub fn press_key(&self, key: &str) {
+        self.with_runner(|servo| {
+            let event = KeyEvent::synthesized(key.to_string(), false);
+            servo.key_press(&event);
+            servo.key_release(&event);
+        });
+    }
+
+    /// Types `text` into the focused element, one character at a time.
+    pub fn type_text(&self, text: &str) {
+        self.with_runner(|servo| {
+            for character in text.chars() {
+                let event = KeyEvent::synthesized(character.to_string(), true);
+                servo.key_press(&event);
+                servo.key_release(&event);
+            }
+        });
+    }
+
+    fn set_loading(&self, is_loading: bool) {
+        if self.imp().is_loading.replace(is_loading) != is_loading {
+            self.notify_is_loading();
+        }
+    }
+
+    /// Ends `load_id` with `result`, along with the loads it replaced.
+    fn load_finished(&self, load_id: u64, result: Result<(), glib::Error>) {
+        let imp = self.imp();
+        let pending_load_id = imp.load_id.get();
+        if load_id == 0 && pending_load_id != 0 {
+            // The page that was there before the load asked for
+            return;
+        }
+        if load_id == pending_load_id {
+            imp.load_id.set(0);
+            self.set_loading(false);
+        }
+
+        let waiters = imp.load_waiters.take();
+        let (finished, waiting): (Vec<_>, Vec<_>) = waiters
+            .into_iter()
+            .partition(|(waiter_load_id, _)| *waiter_load_id <= load_id);
+        imp.load_waiters.replace(waiting);
+        for (_, waiter) in finished {
+            let _ = waiter.try_send(result.clone());
+        }
+    }
+
+    fn process_runner_terminated(&self, reason: TerminationReason, code: i32) {
+        warn!("Servo runner terminated: {reason:?} ({code})");
+
+        let imp = self.imp();
+        // Pending requests fail as their senders go away
+        imp.servo_runner.replace(None);
+        imp.load_waiters.take();
+        if imp.is_loading.replace(false) {
+            self.notify_is_loading();
+        }
+
+        self.emit_by_name::<()>("web-process-terminated", &[&reason, &code]);
+    }
+
+    fn process_runner_failed(&self, err: glib::Error) {
+        warn!("Servo runner can't be used: {err}");
+
+        let imp = self.imp();
+        // Stored first so that pending requests fail with it
+        imp.failure.replace(Some(err.clone()));
+        imp.servo_runner.replace(None);
+        imp.load_waiters.take();
+        if imp.is_loading.replace(false) {
+            self.notify_is_loading();
+        }
+
+        self.emit_by_name::<()>("web-process-failed", &[&err]);
+    }
+
+    fn process_servo_event(&self, event: ServoEvent) {
+        let Some(event_type) = event.event else {
+            return;
+        };
+
+        let imp = self.imp();
+        match event_type {
+            servo_event::Event::TitleChanged(title_changed) => {
+                let title = Some(title_changed.title).filter(|title| !title.is_empty());
+                if *imp.title.borrow() != title {
+                    imp.title.replace(title);
+                    self.notify_title();
+                }
+            }
+            servo_event::Event::UrlChanged(url_changed) => {
+                let uri = Some(url_changed.url);
+                if *imp.uri.borrow() != uri {
+                    imp.uri.replace(uri);
+                    self.notify_uri();
+                }
+            }
+            servo_event::Event::LoadStart(_) => self.set_loading(true),
+            servo_event::Event::LoadEnd(load_end) => self.load_finished(load_end.load_id, Ok(())),
+            servo_event::Event::LoadFailed(load_failed) => self.load_finished(
+                load_failed.load_id,
+                Err(glib::Error::new(
+                    gio::IOErrorEnum::Failed,
+                    &load_failed.error,
+                )),
+            ),
+            servo_event::Event::JavascriptResult(javascript_result) => {
+                self.with_runner(|servo| servo.javascript_result(javascript_result));
+            }
+            servo_event::Event::SnapshotResult(snapshot_result) => {
+                self.with_runner(|servo| servo.snapshot_result(snapshot_result));
+            }
+            // Nobody is there to decide or answer, so go with what leaves
+            // the page running
+            servo_event::Event::NavigationRequest(navigation_request) => {
+                self.with_runner(|servo| {
+                    servo.navigation_decision(navigation_request.request_id, true)
+                });
+            }
+            servo_event::Event::ShowDialog(show_dialog) => {
+                let confirmed = show_dialog.dialog_type == DialogType::BeforeUnload as i32;
+                self.with_runner(|servo| {
+                    servo.dialog_response(show_dialog.request_id, confirmed, "")
+                });
+            }
+            servo_event::Event::FileChooserRequest(file_chooser_request) => {
+                self.with_runner(|servo| {
+                    servo.file_chooser_response(file_chooser_request.request_id, &[])
+                });
+            }
+            servo_event::Event::SchemeRequest(scheme_request) => {
+                self.with_runner(|servo| {
+                    servo.scheme_response(
+                        scheme_request.request_id,
+                        0,
+                        "",
+                        &[],
+                        "Unknown URI scheme",
+                    )
+                });
+            }
+            servo_event::Event::CreateRequest(create_request) => {
+                self.with_runner(|servo| servo.create_response(create_request.request_id, false));
+            }
+            servo_event::Event::LogMessage(log_msg) => {
+                RunnerProcess::handle_log_message(LogLevel::from(log_msg.level), &log_msg.message);
+            }
+            // Frames are only read back through screenshots
+            servo_event::Event::FrameReady(frame_ready) => {
+                self.with_runner(|servo| {
+                    servo.release_frame(frame_ready.frame_buffer_id, frame_ready.buffer_index)
+                });
+            }
+            servo_event::Event::FrameDamage(frame_damage) => {
+                self.with_runner(|servo| {
+                    servo.release_frame(frame_damage.frame_buffer_id, frame_damage.buffer_index)
+                });
+            }
+            // The rest is about the widget
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,65 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use std::cell::{Cell, RefCell};
+
+mod imp {
+    use super::*;
+
+    #[derive(Default, glib::Properties)]
+    #[properties(wrapper_type = super::HitTestResult)]
+    pub struct HitTestResult {
+        /// The target of the link under the pointer
+        #[property(get, nullable)]
+        pub link_uri: RefCell<Option<String>>,
+        /// The source of the image under the pointer
+        #[property(get, nullable)]
+        pub image_uri: RefCell<Option<String>>,
+        /// The text selected in the page
+        #[property(get, nullable)]
+        pub selected_text: RefCell<Option<String>>,
+        /// Whether the pointer is over an editable field
+        #[property(get)]
+        pub editable: Cell<bool>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for HitTestResult {
+        const NAME: &'static str = "HitTestResult";
+        type Type = super::HitTestResult;
+        type ParentType = glib::Object;
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for HitTestResult {}
+}
+
+glib::wrapper! {
+    /// What was under the pointer when a context menu was requested.
+    pub struct HitTestResult(ObjectSubclass<imp::HitTestResult>);
+}
+
+fn non_empty(value: String) -> Option<String> {
+    (!value.is_empty()).then_some(value)
+}
+
+impl HitTestResult {
+    pub(crate) fn new(
+        link_uri: String,
+        image_uri: String,
+        selected_text: String,
+        editable: bool,
+    ) -> Self {
+        let result: Self = glib::Object::builder().build();
+        let imp = result.imp();
+        imp.link_uri.replace(non_empty(link_uri));
+        imp.image_uri.replace(non_empty(image_uri));
+        imp.selected_text.replace(non_empty(selected_text));
+        imp.editable.set(editable);
+        result
+    }
+}
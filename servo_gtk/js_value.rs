@@ -0,0 +1,62 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::proto_ipc::{self, js_value};
+use glib::prelude::*;
+use std::collections::BTreeMap;
+
+/// The result of evaluating a script in a web page.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsValue {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsValue>),
+    Object(BTreeMap<String, JsValue>),
+}
+
+impl JsValue {
+    /// Converts the value to a `GVariant`. `null` becomes an empty maybe,
+    /// arrays become `av` and objects `a{sv}`.
+    pub fn to_variant(&self) -> glib::Variant {
+        match self {
+            JsValue::Null => None::<glib::Variant>.to_variant(),
+            JsValue::Boolean(value) => value.to_variant(),
+            JsValue::Number(value) => value.to_variant(),
+            JsValue::String(value) => value.to_variant(),
+            JsValue::Array(items) => items
+                .iter()
+                .map(JsValue::to_variant)
+                .collect::<Vec<_>>()
+                .to_variant(),
+            JsValue::Object(properties) => properties
+                .iter()
+                .map(|(name, value)| (name.clone(), value.to_variant()))
+                .collect::<BTreeMap<_, _>>()
+                .to_variant(),
+        }
+    }
+}
+
+impl From<proto_ipc::JsValue> for JsValue {
+    fn from(value: proto_ipc::JsValue) -> Self {
+        match value.value {
+            None | Some(js_value::Value::Null(_)) => JsValue::Null,
+            Some(js_value::Value::Boolean(value)) => JsValue::Boolean(value),
+            Some(js_value::Value::Number(value)) => JsValue::Number(value),
+            Some(js_value::Value::String(value)) => JsValue::String(value),
+            Some(js_value::Value::Array(array)) => {
+                JsValue::Array(array.items.into_iter().map(JsValue::from).collect())
+            }
+            Some(js_value::Value::Object(object)) => JsValue::Object(
+                object
+                    .properties
+                    .into_iter()
+                    .map(|(name, value)| (name, JsValue::from(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
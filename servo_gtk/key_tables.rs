@@ -4,6 +4,7 @@
 
 use glib::translate::{FromGlib, IntoGlib};
 use gtk::gdk;
+use keyboard_types::{Code, Modifiers};
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -14,9 +15,222 @@ pub enum KeyLocation {
     Numpad,
 }
 
+/// A key press or release to send to the page.
+#[derive(Debug, Clone)]
+pub struct KeyEvent {
+    /// The DOM `KeyboardEvent.key` value
+    pub key: String,
+    /// Whether `key` is the character typed rather than a key name
+    pub is_character: bool,
+    pub location: KeyLocation,
+    /// The hardware keycode, as given by GDK
+    pub key_code: u32,
+    /// The physical key, independent of the keyboard layout
+    pub code: Code,
+    pub modifiers: Modifiers,
+    /// Whether the key is held down and repeating
+    pub repeat: bool,
+    /// Whether the key is part of a composition, like after a dead key
+    pub is_composing: bool,
+}
+
+impl KeyEvent {
+    /// A key that isn't tied to any hardware key, like the ones synthesized
+    /// by a `crate::HeadlessView`.
+    pub fn synthesized(key: String, is_character: bool) -> Self {
+        Self {
+            key,
+            is_character,
+            location: KeyLocation::Standard,
+            key_code: 0,
+            code: Code::Unidentified,
+            modifiers: Modifiers::empty(),
+            repeat: false,
+            is_composing: false,
+        }
+    }
+}
+
+/// XKB keycodes are evdev ones shifted by 8, for historical X11 reasons.
+const XKB_EVDEV_OFFSET: u32 = 8;
+
+/// The evdev keycodes from `linux/input-event-codes.h` with a DOM code,
+/// following the mapping of the UI Events KeyboardEvent code values spec.
+const EVDEV_CODES: &[(u32, Code)] = &[
+    (1, Code::Escape),
+    (2, Code::Digit1),
+    (3, Code::Digit2),
+    (4, Code::Digit3),
+    (5, Code::Digit4),
+    (6, Code::Digit5),
+    (7, Code::Digit6),
+    (8, Code::Digit7),
+    (9, Code::Digit8),
+    (10, Code::Digit9),
+    (11, Code::Digit0),
+    (12, Code::Minus),
+    (13, Code::Equal),
+    (14, Code::Backspace),
+    (15, Code::Tab),
+    (16, Code::KeyQ),
+    (17, Code::KeyW),
+    (18, Code::KeyE),
+    (19, Code::KeyR),
+    (20, Code::KeyT),
+    (21, Code::KeyY),
+    (22, Code::KeyU),
+    (23, Code::KeyI),
+    (24, Code::KeyO),
+    (25, Code::KeyP),
+    (26, Code::BracketLeft),
+    (27, Code::BracketRight),
+    (28, Code::Enter),
+    (29, Code::ControlLeft),
+    (30, Code::KeyA),
+    (31, Code::KeyS),
+    (32, Code::KeyD),
+    (33, Code::KeyF),
+    (34, Code::KeyG),
+    (35, Code::KeyH),
+    (36, Code::KeyJ),
+    (37, Code::KeyK),
+    (38, Code::KeyL),
+    (39, Code::Semicolon),
+    (40, Code::Quote),
+    (41, Code::Backquote),
+    (42, Code::ShiftLeft),
+    (43, Code::Backslash),
+    (44, Code::KeyZ),
+    (45, Code::KeyX),
+    (46, Code::KeyC),
+    (47, Code::KeyV),
+    (48, Code::KeyB),
+    (49, Code::KeyN),
+    (50, Code::KeyM),
+    (51, Code::Comma),
+    (52, Code::Period),
+    (53, Code::Slash),
+    (54, Code::ShiftRight),
+    (55, Code::NumpadMultiply),
+    (56, Code::AltLeft),
+    (57, Code::Space),
+    (58, Code::CapsLock),
+    (59, Code::F1),
+    (60, Code::F2),
+    (61, Code::F3),
+    (62, Code::F4),
+    (63, Code::F5),
+    (64, Code::F6),
+    (65, Code::F7),
+    (66, Code::F8),
+    (67, Code::F9),
+    (68, Code::F10),
+    (69, Code::NumLock),
+    (70, Code::ScrollLock),
+    (71, Code::Numpad7),
+    (72, Code::Numpad8),
+    (73, Code::Numpad9),
+    (74, Code::NumpadSubtract),
+    (75, Code::Numpad4),
+    (76, Code::Numpad5),
+    (77, Code::Numpad6),
+    (78, Code::NumpadAdd),
+    (79, Code::Numpad1),
+    (80, Code::Numpad2),
+    (81, Code::Numpad3),
+    (82, Code::Numpad0),
+    (83, Code::NumpadDecimal),
+    (85, Code::Lang5),
+    (86, Code::IntlBackslash),
+    (87, Code::F11),
+    (88, Code::F12),
+    (89, Code::IntlRo),
+    (90, Code::Lang3),
+    (91, Code::Lang4),
+    (92, Code::Convert),
+    (93, Code::KanaMode),
+    (94, Code::NonConvert),
+    (96, Code::NumpadEnter),
+    (97, Code::ControlRight),
+    (98, Code::NumpadDivide),
+    (99, Code::PrintScreen),
+    (100, Code::AltRight),
+    (102, Code::Home),
+    (103, Code::ArrowUp),
+    (104, Code::PageUp),
+    (105, Code::ArrowLeft),
+    (106, Code::ArrowRight),
+    (107, Code::End),
+    (108, Code::ArrowDown),
+    (109, Code::PageDown),
+    (110, Code::Insert),
+    (111, Code::Delete),
+    (113, Code::AudioVolumeMute),
+    (114, Code::AudioVolumeDown),
+    (115, Code::AudioVolumeUp),
+    (116, Code::Power),
+    (117, Code::NumpadEqual),
+    (119, Code::Pause),
+    (121, Code::NumpadComma),
+    (122, Code::Lang1),
+    (123, Code::Lang2),
+    (124, Code::IntlYen),
+    (125, Code::MetaLeft),
+    (126, Code::MetaRight),
+    (127, Code::ContextMenu),
+    (128, Code::BrowserStop),
+    (129, Code::Again),
+    (130, Code::Props),
+    (131, Code::Undo),
+    (133, Code::Copy),
+    (134, Code::Open),
+    (135, Code::Paste),
+    (136, Code::Find),
+    (137, Code::Cut),
+    (138, Code::Help),
+    (140, Code::LaunchApp2),
+    (142, Code::Sleep),
+    (143, Code::WakeUp),
+    (155, Code::LaunchMail),
+    (156, Code::BrowserFavorites),
+    (157, Code::LaunchApp1),
+    (158, Code::BrowserBack),
+    (159, Code::BrowserForward),
+    (161, Code::Eject),
+    (163, Code::MediaTrackNext),
+    (164, Code::MediaPlayPause),
+    (165, Code::MediaTrackPrevious),
+    (166, Code::MediaStop),
+    (171, Code::MediaSelect),
+    (172, Code::BrowserHome),
+    (173, Code::BrowserRefresh),
+    (179, Code::NumpadParenLeft),
+    (180, Code::NumpadParenRight),
+    (183, Code::F13),
+    (184, Code::F14),
+    (185, Code::F15),
+    (186, Code::F16),
+    (187, Code::F17),
+    (188, Code::F18),
+    (189, Code::F19),
+    (190, Code::F20),
+    (191, Code::F21),
+    (192, Code::F22),
+    (193, Code::F23),
+    (194, Code::F24),
+    (217, Code::BrowserSearch),
+];
+
+/// Whether `keyval` is one of the dead keys, starting a composition with
+/// the next key, like `dead_acute`.
+pub fn is_dead_key(keyval: u32) -> bool {
+    (gdk::Key::dead_grave.into_glib()..=gdk::Key::dead_greek.into_glib()).contains(&keyval)
+}
+
 pub struct KeyTables {
     keys: HashMap<u32, (&'static str, KeyLocation)>,
     numpad_table: Vec<u32>,
+    codes: HashMap<u32, Code>,
 }
 
 impl KeyTables {
@@ -572,12 +786,24 @@ impl KeyTables {
             gdk::Key::KP_9.into_glib(),
         ];
 
-        Self { keys, numpad_table }
+        // GDK hands out XKB keycodes on Linux
+        let codes = EVDEV_CODES
+            .iter()
+            .map(|(evdev_code, code)| (evdev_code + XKB_EVDEV_OFFSET, *code))
+            .collect();
+
+        Self {
+            keys,
+            numpad_table,
+            codes,
+        }
     }
 
     pub fn key_from_keyval(&self, keyval: u32) -> Option<(String, bool, KeyLocation)> {
         if let Some((key_name, location)) = self.keys.get(&keyval) {
             Some((key_name.to_string(), false, location.clone()))
+        } else if is_dead_key(keyval) {
+            Some(("Dead".to_string(), false, KeyLocation::Standard))
         } else {
             // Try to convert to unicode character
             let gdk_key = unsafe { gdk::Key::from_glib(keyval) };
@@ -594,6 +820,14 @@ impl KeyTables {
             None
         }
     }
+
+    /// The physical key at hardware `keycode`, as given by GDK.
+    pub fn code_from_keycode(&self, keycode: u32) -> Code {
+        self.codes
+            .get(&keycode)
+            .copied()
+            .unwrap_or(Code::Unidentified)
+    }
 }
 
 impl Default for KeyTables {
@@ -601,3 +835,80 @@ impl Default for KeyTables {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keycodes_are_xkb_ones() {
+        let tables = KeyTables::new();
+        for (keycode, code) in [
+            // Evdev 1
+            (9, Code::Escape),
+            // Letters
+            (24, Code::KeyQ),
+            (38, Code::KeyA),
+            (52, Code::KeyZ),
+            (58, Code::KeyM),
+            // Arrows
+            (111, Code::ArrowUp),
+            (113, Code::ArrowLeft),
+            (114, Code::ArrowRight),
+            (116, Code::ArrowDown),
+            // Numpad
+            (79, Code::Numpad7),
+            (90, Code::Numpad0),
+            (91, Code::NumpadDecimal),
+            (104, Code::NumpadEnter),
+            (106, Code::NumpadDivide),
+        ] {
+            assert_eq!(tables.code_from_keycode(keycode), code, "{keycode}");
+        }
+    }
+
+    #[test]
+    fn unmapped_keycodes_are_unidentified() {
+        let tables = KeyTables::new();
+        // Below the offset, evdev 0 and far past the table
+        for keycode in [0, 1, 8, 9999] {
+            assert_eq!(
+                tables.code_from_keycode(keycode),
+                Code::Unidentified,
+                "{keycode}"
+            );
+        }
+    }
+
+    #[test]
+    fn evdev_codes_are_unique() {
+        let mut seen = std::collections::HashSet::new();
+        for (evdev_code, code) in EVDEV_CODES {
+            assert!(
+                seen.insert(evdev_code),
+                "{evdev_code} maps to {code:?} twice"
+            );
+        }
+    }
+
+    #[test]
+    fn dead_keys_are_detected() {
+        for (key, dead) in [
+            (gdk::Key::dead_grave, true),
+            (gdk::Key::dead_acute, true),
+            (gdk::Key::dead_circumflex, true),
+            (gdk::Key::dead_greek, true),
+            (gdk::Key::a, false),
+            (gdk::Key::Shift_L, false),
+            (gdk::Key::Multi_key, false),
+        ] {
+            assert_eq!(is_dead_key(key.into_glib()), dead, "{key:?}");
+        }
+
+        let tables = KeyTables::new();
+        assert_eq!(
+            tables.key_from_keyval(gdk::Key::dead_acute.into_glib()),
+            Some(("Dead".to_string(), false, KeyLocation::Standard))
+        );
+    }
+}
@@ -2,9 +2,32 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+pub mod download;
+pub mod file_chooser_request;
+pub mod frame_buffer;
+pub mod headless_view;
+pub mod hit_test_result;
+pub mod js_value;
 pub mod key_tables;
+pub mod modifiers;
+pub mod navigation_policy;
 pub mod proto_ipc;
+pub mod script_dialog;
 pub mod servo_runner;
+pub mod snapshot;
+pub mod uri_scheme;
+pub mod web_context;
 pub mod web_view;
 
+pub use download::Download;
+pub use file_chooser_request::FileChooserRequest;
+pub use headless_view::HeadlessView;
+pub use hit_test_result::HitTestResult;
+pub use js_value::JsValue;
+pub use navigation_policy::{NavigationPolicyDecision, NavigationType};
+pub use script_dialog::{ScriptDialog, ScriptDialogType};
+pub use servo_runner::TerminationReason;
+pub use snapshot::{SnapshotOptions, SnapshotRegion};
+pub use uri_scheme::UriSchemeRequest;
+pub use web_context::WebContext;
 pub use web_view::WebView;
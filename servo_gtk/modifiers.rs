@@ -0,0 +1,173 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Translation between GDK modifier masks and the DOM modifiers pages see,
+//! which the protocol carries.
+
+use gtk::gdk;
+use keyboard_types::Modifiers;
+
+/// Lock states and AltGr, which `gdk::ModifierType` doesn't carry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LockState {
+    pub num_lock: bool,
+    pub scroll_lock: bool,
+    /// Whether ISO_Level3_Shift, AltGr on most layouts, is held down
+    pub alt_graph: bool,
+}
+
+impl LockState {
+    /// Reads the lock states of `keyboard`, the keyboard of the seat.
+    pub fn from_keyboard(keyboard: &gdk::Device, alt_graph: bool) -> Self {
+        Self {
+            num_lock: keyboard.num_lock_state(),
+            scroll_lock: keyboard.scroll_lock_state(),
+            alt_graph,
+        }
+    }
+}
+
+/// The DOM modifiers for the GDK modifier `state`.
+///
+/// Super is reported as Meta, like browsers on Linux do. GDK's own Meta is
+/// left out as it usually comes along with Alt.
+pub fn dom_modifiers(state: gdk::ModifierType, locks: LockState) -> Modifiers {
+    let mut modifiers = Modifiers::empty();
+    modifiers.set(
+        Modifiers::SHIFT,
+        state.contains(gdk::ModifierType::SHIFT_MASK),
+    );
+    modifiers.set(
+        Modifiers::CONTROL,
+        state.contains(gdk::ModifierType::CONTROL_MASK),
+    );
+    modifiers.set(Modifiers::ALT, state.contains(gdk::ModifierType::ALT_MASK));
+    modifiers.set(
+        Modifiers::META,
+        state.contains(gdk::ModifierType::SUPER_MASK),
+    );
+    modifiers.set(
+        Modifiers::HYPER,
+        state.contains(gdk::ModifierType::HYPER_MASK),
+    );
+    modifiers.set(
+        Modifiers::CAPS_LOCK,
+        state.contains(gdk::ModifierType::LOCK_MASK),
+    );
+    modifiers.set(Modifiers::NUM_LOCK, locks.num_lock);
+    modifiers.set(Modifiers::SCROLL_LOCK, locks.scroll_lock);
+    modifiers.set(Modifiers::ALT_GRAPH, locks.alt_graph);
+    modifiers
+}
+
+/// The modifier `keyval` itself switches on while held down, which GDK
+/// only reports in the state of the events after its press.
+pub fn key_modifier(keyval: gdk::Key) -> Modifiers {
+    match keyval {
+        gdk::Key::Shift_L | gdk::Key::Shift_R => Modifiers::SHIFT,
+        gdk::Key::Control_L | gdk::Key::Control_R => Modifiers::CONTROL,
+        gdk::Key::Alt_L | gdk::Key::Alt_R => Modifiers::ALT,
+        gdk::Key::Super_L | gdk::Key::Super_R => Modifiers::META,
+        gdk::Key::Hyper_L | gdk::Key::Hyper_R => Modifiers::HYPER,
+        gdk::Key::ISO_Level3_Shift => Modifiers::ALT_GRAPH,
+        _ => Modifiers::empty(),
+    }
+}
+
+/// The GDK modifier mask for the DOM `modifiers`, for the parts GDK has a
+/// mask for.
+pub fn gdk_modifiers(modifiers: Modifiers) -> gdk::ModifierType {
+    let mut state = gdk::ModifierType::empty();
+    state.set(
+        gdk::ModifierType::SHIFT_MASK,
+        modifiers.contains(Modifiers::SHIFT),
+    );
+    state.set(
+        gdk::ModifierType::CONTROL_MASK,
+        modifiers.contains(Modifiers::CONTROL),
+    );
+    state.set(
+        gdk::ModifierType::ALT_MASK,
+        modifiers.contains(Modifiers::ALT),
+    );
+    state.set(
+        gdk::ModifierType::SUPER_MASK,
+        modifiers.contains(Modifiers::META),
+    );
+    state.set(
+        gdk::ModifierType::HYPER_MASK,
+        modifiers.contains(Modifiers::HYPER),
+    );
+    state.set(
+        gdk::ModifierType::LOCK_MASK,
+        modifiers.contains(Modifiers::CAPS_LOCK),
+    );
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_become_dom_modifiers() {
+        let state = gdk::ModifierType::SHIFT_MASK
+            | gdk::ModifierType::CONTROL_MASK
+            | gdk::ModifierType::ALT_MASK
+            | gdk::ModifierType::SUPER_MASK;
+        assert_eq!(
+            dom_modifiers(state, LockState::default()),
+            Modifiers::SHIFT | Modifiers::CONTROL | Modifiers::ALT | Modifiers::META
+        );
+        assert_eq!(
+            dom_modifiers(gdk::ModifierType::META_MASK, LockState::default()),
+            Modifiers::empty()
+        );
+    }
+
+    #[test]
+    fn locks_become_dom_modifiers() {
+        let locks = LockState {
+            num_lock: true,
+            ..LockState::default()
+        };
+        assert_eq!(
+            dom_modifiers(gdk::ModifierType::LOCK_MASK, locks),
+            Modifiers::CAPS_LOCK | Modifiers::NUM_LOCK
+        );
+    }
+
+    #[test]
+    fn alt_graph_comes_from_the_lock_state() {
+        let locks = LockState {
+            alt_graph: true,
+            ..LockState::default()
+        };
+        assert_eq!(
+            dom_modifiers(gdk::ModifierType::empty(), locks),
+            Modifiers::ALT_GRAPH
+        );
+        assert_eq!(
+            key_modifier(gdk::Key::ISO_Level3_Shift),
+            Modifiers::ALT_GRAPH
+        );
+    }
+
+    #[test]
+    fn masks_survive_the_round_trip() {
+        let state = gdk::ModifierType::SHIFT_MASK
+            | gdk::ModifierType::CONTROL_MASK
+            | gdk::ModifierType::ALT_MASK
+            | gdk::ModifierType::SUPER_MASK
+            | gdk::ModifierType::HYPER_MASK
+            | gdk::ModifierType::LOCK_MASK;
+        for bit in state.iter() {
+            assert_eq!(gdk_modifiers(dom_modifiers(bit, LockState::default())), bit);
+        }
+        assert_eq!(
+            gdk_modifiers(dom_modifiers(state, LockState::default())),
+            state
+        );
+    }
+}
@@ -0,0 +1,116 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::web_view::WebView;
+use gtk::gdk;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use std::cell::{Cell, RefCell};
+
+/// What started a navigation.
+///
+/// Servo doesn't say why a page navigates, so the only thing told apart is
+/// whether a click in the page came right before. Form submissions, script
+/// navigations and anything else are [`NavigationType::Other`]. Redirects
+/// and the first load of windows opened by the page never reach
+/// `decide-policy`, the latter go through `create` instead.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
+#[enum_type(name = "ServoGtkNavigationType")]
+pub enum NavigationType {
+    /// The navigation followed a click in the page.
+    LinkClicked,
+    /// Scripts, redirects and anything else.
+    #[default]
+    Other,
+}
+
+mod imp {
+    use super::*;
+
+    #[derive(Default, glib::Properties)]
+    #[properties(wrapper_type = super::NavigationPolicyDecision)]
+    pub struct NavigationPolicyDecision {
+        pub request_id: Cell<u64>,
+        pub web_view: glib::WeakRef<WebView>,
+        pub decided: Cell<bool>,
+        /// The URI being navigated to
+        #[property(get)]
+        pub uri: RefCell<String>,
+        /// What started the navigation
+        #[property(get, builder(NavigationType::default()))]
+        pub navigation_type: Cell<NavigationType>,
+        /// The mouse button that was clicked, or 0
+        #[property(get)]
+        pub mouse_button: Cell<u32>,
+        /// The modifiers that were held down
+        #[property(get)]
+        pub modifiers: Cell<gdk::ModifierType>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for NavigationPolicyDecision {
+        const NAME: &'static str = "NavigationPolicyDecision";
+        type Type = super::NavigationPolicyDecision;
+        type ParentType = glib::Object;
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for NavigationPolicyDecision {
+        fn dispose(&self) {
+            // Don't leave the navigation waiting for the runner's timeout
+            self.obj().decide(true);
+        }
+    }
+}
+
+glib::wrapper! {
+    /// A pending navigation, passed to the `decide-policy` signal.
+    ///
+    /// There is no decision to open the URI somewhere else, handlers that
+    /// want to, for example for middle clicks, should ignore the navigation
+    /// and load the URI in another view themselves.
+    pub struct NavigationPolicyDecision(ObjectSubclass<imp::NavigationPolicyDecision>);
+}
+
+impl NavigationPolicyDecision {
+    pub(crate) fn new(
+        request_id: u64,
+        web_view: &WebView,
+        uri: String,
+        navigation_type: NavigationType,
+        mouse_button: u32,
+        modifiers: gdk::ModifierType,
+    ) -> Self {
+        let decision: Self = glib::Object::builder().build();
+        let imp = decision.imp();
+        imp.request_id.set(request_id);
+        imp.web_view.set(Some(web_view));
+        imp.uri.replace(uri);
+        imp.navigation_type.set(navigation_type);
+        imp.mouse_button.set(mouse_button);
+        imp.modifiers.set(modifiers);
+        decision
+    }
+
+    fn decide(&self, allow: bool) {
+        let imp = self.imp();
+        if imp.decided.replace(true) {
+            return;
+        }
+        if let Some(web_view) = imp.web_view.upgrade() {
+            web_view.finish_navigation_decision(imp.request_id.get(), allow);
+        }
+    }
+
+    /// Lets the navigation happen.
+    pub fn allow(&self) {
+        self.decide(true);
+    }
+
+    /// Cancels the navigation.
+    pub fn ignore(&self) {
+        self.decide(false);
+    }
+}
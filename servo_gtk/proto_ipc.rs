@@ -2,7 +2,60 @@ use prost::Message;
 
 include!(concat!(env!("OUT_DIR"), "/servo_ipc.rs"));
 
+/// Version of the protocol, bumped whenever messages change in a way older
+/// peers can't make sense of.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// The Servo revision the crate is built against.
+pub const ENGINE_REVISION: &str = env!("SERVO_GTK_ENGINE_REVISION");
+
+/// Bits for the optional parts of the protocol, exchanged in `Hello`.
+///
+/// A peer lacking some is still talked to, with what depends on them
+/// turned off.
+pub mod features {
+    /// Several web views share one runner process.
+    pub const MULTIPLE_WEB_VIEWS: u64 = 1 << 0;
+    /// Downloads the app starts.
+    pub const DOWNLOADS: u64 = 1 << 1;
+    /// Web views opened by pages.
+    pub const POPUPS: u64 = 1 << 2;
+    /// Snapshots of the visible page and the whole document.
+    pub const SNAPSHOTS: u64 = 1 << 3;
+    /// Text composed through input methods.
+    pub const INPUT_METHODS: u64 = 1 << 4;
+
+    /// Everything this build supports.
+    pub const ALL: u64 = MULTIPLE_WEB_VIEWS | DOWNLOADS | POPUPS | SNAPSHOTS | INPUT_METHODS;
+}
+
+impl Hello {
+    /// What this build of the crate has to say.
+    pub fn current() -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            engine_revision: ENGINE_REVISION.to_string(),
+            features: features::ALL,
+        }
+    }
+}
+
 impl ServoAction {
+    /// Whether the runner starts Servo to handle the action. Until then it
+    /// collects custom schemes, which Servo only takes when it starts, and
+    /// holds back the views and their sizes.
+    pub fn starts_engine(&self) -> bool {
+        !matches!(
+            self.action,
+            Some(
+                servo_action::Action::Hello(_)
+                    | servo_action::Action::RegisterUriScheme(_)
+                    | servo_action::Action::CreateWebView(_)
+                    | servo_action::Action::Resize(_)
+            )
+        )
+    }
+
     pub fn encode_to_vec(&self) -> Vec<u8> {
         let mut buf = Vec::new();
         self.encode(&mut buf).unwrap();
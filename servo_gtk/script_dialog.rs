@@ -0,0 +1,217 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::web_view::WebView;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use std::cell::{Cell, RefCell};
+
+/// The kind of dialog a page asked for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
+#[enum_type(name = "ServoGtkScriptDialogType")]
+pub enum ScriptDialogType {
+    /// `alert()`, which only needs to be acknowledged.
+    #[default]
+    Alert,
+    /// `confirm()`, accepted or dismissed.
+    Confirm,
+    /// `prompt()`, which also asks for some text.
+    Prompt,
+    /// Confirmation to leave a page with a `beforeunload` handler.
+    BeforeUnload,
+}
+
+mod imp {
+    use super::*;
+
+    #[derive(Default, glib::Properties)]
+    #[properties(wrapper_type = super::ScriptDialog)]
+    pub struct ScriptDialog {
+        pub request_id: Cell<u64>,
+        pub web_view: glib::WeakRef<WebView>,
+        pub answered: Cell<bool>,
+        /// The kind of dialog
+        #[property(get, builder(ScriptDialogType::default()))]
+        pub dialog_type: Cell<ScriptDialogType>,
+        /// The message passed by the page
+        #[property(get)]
+        pub message: RefCell<String>,
+        /// The text a prompt starts with
+        #[property(get)]
+        pub default_text: RefCell<String>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ScriptDialog {
+        const NAME: &'static str = "ScriptDialog";
+        type Type = super::ScriptDialog;
+        type ParentType = glib::Object;
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for ScriptDialog {
+        fn dispose(&self) {
+            // The page is blocked until the dialog gets an answer
+            self.obj().dismiss();
+        }
+    }
+}
+
+glib::wrapper! {
+    /// A dialog requested by a page, passed to the `script-dialog` signal.
+    ///
+    /// The page stays blocked until the dialog is answered with one of
+    /// `accept`, `accept_with_text` or `dismiss`. A dialog that gets dropped
+    /// without an answer is dismissed.
+    pub struct ScriptDialog(ObjectSubclass<imp::ScriptDialog>);
+}
+
+impl ScriptDialog {
+    pub(crate) fn new(
+        request_id: u64,
+        web_view: &WebView,
+        dialog_type: ScriptDialogType,
+        message: String,
+        default_text: String,
+    ) -> Self {
+        let dialog: Self = glib::Object::builder().build();
+        let imp = dialog.imp();
+        imp.request_id.set(request_id);
+        imp.web_view.set(Some(web_view));
+        imp.dialog_type.set(dialog_type);
+        imp.message.replace(message);
+        imp.default_text.replace(default_text);
+        dialog
+    }
+
+    fn answer(&self, confirmed: bool, text: &str) {
+        let imp = self.imp();
+        if imp.answered.replace(true) {
+            return;
+        }
+        if let Some(web_view) = imp.web_view.upgrade() {
+            web_view.finish_script_dialog(imp.request_id.get(), confirmed, text);
+        }
+    }
+
+    /// Confirms the dialog. Prompts return their default text.
+    pub fn accept(&self) {
+        self.answer(true, &self.default_text());
+    }
+
+    /// Confirms a prompt with `text`.
+    pub fn accept_with_text(&self, text: &str) {
+        self.answer(true, text);
+    }
+
+    /// Cancels the dialog, or stays on the page for `beforeunload`.
+    pub fn dismiss(&self) {
+        self.answer(false, "");
+    }
+
+    /// Shows the dialog with stock GTK widgets, used when no `script-dialog`
+    /// handler takes care of it.
+    pub(crate) fn run_default(&self, parent: Option<gtk::Window>) {
+        let (message, detail, buttons) = match self.dialog_type() {
+            ScriptDialogType::Alert => (self.message(), String::new(), vec!["OK"]),
+            ScriptDialogType::Confirm => (self.message(), String::new(), vec!["Cancel", "OK"]),
+            ScriptDialogType::Prompt => {
+                self.run_prompt(parent);
+                return;
+            }
+            ScriptDialogType::BeforeUnload => (
+                "Leave this page?".to_string(),
+                "Changes you made may not be saved.".to_string(),
+                vec!["Stay", "Leave"],
+            ),
+        };
+
+        let accept_button = buttons.len() as i32 - 1;
+        let alert = gtk::AlertDialog::builder()
+            .modal(true)
+            .message(message)
+            .detail(detail)
+            .buttons(buttons)
+            .cancel_button(0)
+            .default_button(accept_button)
+            .build();
+
+        let dialog = self.clone();
+        glib::spawn_future_local(async move {
+            match alert.choose_future(parent.as_ref()).await {
+                Ok(button) if button == accept_button => dialog.accept(),
+                _ => dialog.dismiss(),
+            }
+        });
+    }
+
+    // gtk::AlertDialog can't hold an entry, so prompts get a small window
+    fn run_prompt(&self, parent: Option<gtk::Window>) {
+        let window = gtk::Window::builder()
+            .modal(true)
+            .resizable(false)
+            .default_width(360)
+            .build();
+        window.set_transient_for(parent.as_ref());
+
+        let label = gtk::Label::builder()
+            .label(self.message())
+            .wrap(true)
+            .xalign(0.0)
+            .build();
+        let entry = gtk::Entry::builder()
+            .text(self.default_text())
+            .activates_default(true)
+            .build();
+
+        let cancel_button = gtk::Button::with_label("Cancel");
+        let ok_button = gtk::Button::with_label("OK");
+        ok_button.add_css_class("suggested-action");
+        window.set_default_widget(Some(&ok_button));
+
+        let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        button_box.set_halign(gtk::Align::End);
+        button_box.append(&cancel_button);
+        button_box.append(&ok_button);
+
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 12);
+        content.set_margin_top(18);
+        content.set_margin_bottom(18);
+        content.set_margin_start(18);
+        content.set_margin_end(18);
+        content.append(&label);
+        content.append(&entry);
+        content.append(&button_box);
+        window.set_child(Some(&content));
+
+        ok_button.connect_clicked(glib::clone!(
+            #[strong(rename_to = dialog)]
+            self,
+            #[weak]
+            window,
+            #[weak]
+            entry,
+            move |_| {
+                dialog.accept_with_text(&entry.text());
+                window.close();
+            }
+        ));
+        cancel_button.connect_clicked(glib::clone!(
+            #[weak]
+            window,
+            move |_| window.close()
+        ));
+        window.connect_close_request(glib::clone!(
+            #[strong(rename_to = dialog)]
+            self,
+            move |_| {
+                dialog.dismiss();
+                glib::Propagation::Proceed
+            }
+        ));
+
+        window.present();
+    }
+}
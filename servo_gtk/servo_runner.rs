@@ -2,14 +2,27 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::key_tables::KeyLocation;
+use crate::frame_buffer::{FRAME_SOCKET_FD, FrameBuffer, INITIAL_FRAME_SIZE};
+use crate::js_value::JsValue;
+use crate::key_tables::{KeyEvent, KeyLocation};
+use crate::snapshot::{SnapshotOptions, SnapshotRegion};
 use async_channel;
 use gio::prelude::*;
-use gio::{OutputStream, Subprocess, SubprocessFlags, SubprocessLauncher};
+use gio::{Subprocess, SubprocessFlags, SubprocessLauncher};
 use glib::{debug, error, info, warn};
-use std::ffi::OsStr;
+use gtk::gdk;
+use keyboard_types::Modifiers;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
-use crate::proto_ipc::{ServoAction, ServoEvent, servo_action};
+use crate::proto_ipc::{
+    ENGINE_REVISION, Hello, JavaScriptResult, PROTOCOL_VERSION, ScrollPhase, ServoAction,
+    ServoEvent, SnapshotFormat, SnapshotResult, features, java_script_result, servo_action,
+    servo_event,
+};
 
 const G_LOG_DOMAIN: &str = "ServoGtk";
 
@@ -33,36 +46,147 @@ impl From<i32> for LogLevel {
     }
 }
 
-pub struct ServoRunner {
-    stdin: OutputStream,
+/// Why the runner process went away without being asked to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, glib::Enum)]
+#[enum_type(name = "ServoGtkTerminationReason")]
+pub enum TerminationReason {
+    /// The runner panicked.
+    Crashed,
+    /// The runner exited with an unexpected exit code.
+    Exited,
+    /// The runner was killed by a signal.
+    Signaled,
+}
+
+// Exit code of a Rust process that panicked
+const PANIC_EXIT_CODE: i32 = 101;
+
+/// Checks that the runner greeting with `event` can be talked to, and
+/// returns the optional features both sides support.
+fn check_hello(event: &ServoEvent) -> Result<u64, glib::Error> {
+    let Some(servo_event::Event::Hello(hello)) = &event.event else {
+        return Err(glib::Error::new(
+            gio::IOErrorEnum::NotSupported,
+            "The web process doesn't speak the servo-gtk protocol, it may be from an older version",
+        ));
+    };
+
+    if hello.protocol_version != PROTOCOL_VERSION {
+        return Err(glib::Error::new(
+            gio::IOErrorEnum::NotSupported,
+            &format!(
+                "The web process speaks version {} of the protocol instead of {PROTOCOL_VERSION}",
+                hello.protocol_version
+            ),
+        ));
+    }
+
+    let missing_features = features::ALL & !hello.features;
+    if missing_features != 0 {
+        info!("The web process lacks features {missing_features:#x}, they are turned off");
+    }
+
+    // Only the messages matter, a runner built against another Servo can
+    // still work
+    if hello.engine_revision != ENGINE_REVISION {
+        warn!(
+            "The web process is built against Servo {} instead of {ENGINE_REVISION}",
+            hello.engine_revision
+        );
+    }
+
+    Ok(features::ALL & hello.features)
+}
+
+/// A runner process, shared by the views of a [`crate::WebContext`].
+///
+/// The process shuts down once the last [`ServoRunner`] using it is gone.
+pub struct RunnerProcess {
+    action_sender: async_channel::Sender<ServoAction>,
     event_receiver: async_channel::Receiver<ServoEvent>,
+    termination_receiver: async_channel::Receiver<(TerminationReason, i32)>,
+    failure_receiver: async_channel::Receiver<glib::Error>,
+    frame_socket: OwnedFd,
+    shutting_down: Rc<Cell<bool>>,
+    /// The [`features`] both sides support
+    features: Rc<Cell<u64>>,
+    /// The custom schemes sent to the runner
+    uri_schemes: RefCell<HashSet<String>>,
+    /// Whether the runner started Servo, after which it takes no more
+    /// schemes
+    engine_started: Cell<bool>,
     _subprocess: Subprocess,
 }
 
-#[allow(clippy::new_without_default)]
-impl ServoRunner {
-    pub fn new() -> Self {
+impl RunnerProcess {
+    pub fn new(runner_path: &Path) -> Result<Self, glib::Error> {
+        let (frame_socket, runner_frame_socket) = FrameBuffer::socket_pair().map_err(|err| {
+            glib::Error::new(
+                gio::IOErrorEnum::Failed,
+                &format!("Failed to create frame socket: {err}"),
+            )
+        })?;
+
         let launcher =
             SubprocessLauncher::new(SubprocessFlags::STDIN_PIPE | SubprocessFlags::STDOUT_PIPE);
-        let subprocess = launcher
-            .spawn(&[
-                OsStr::new("cargo"),
-                OsStr::new("run"),
-                OsStr::new("--bin"),
-                OsStr::new("servo-runner"),
-            ])
-            .expect("Failed to spawn servo-runner process");
+        launcher.take_fd(runner_frame_socket, unsafe {
+            BorrowedFd::borrow_raw(FRAME_SOCKET_FD)
+        });
+
+        info!("Spawning {}", runner_path.display());
+        let subprocess = launcher.spawn(&[runner_path.as_os_str()])?;
 
         let stdin = subprocess.stdin_pipe().expect("Failed to get stdin");
         let stdout = subprocess.stdout_pipe().expect("Failed to get stdout");
 
+        let (action_sender, action_receiver) = async_channel::unbounded::<ServoAction>();
+
+        // Greet the runner before anything else so it can check it
+        // understands what follows
+        let _ = action_sender.try_send(ServoAction {
+            webview_id: 0,
+            action: Some(servo_action::Action::Hello(Hello::current())),
+        });
+
+        // Async task writing actions to the process one at a time, so that
+        // they are neither reordered nor interleaved
+        glib::spawn_future_local(async move {
+            while let Ok(action) = action_receiver.recv().await {
+                let encoded = action.encode_to_vec();
+                let len = (encoded.len() as u32).to_le_bytes();
+                if stdin
+                    .write_all_future(len.to_vec(), glib::Priority::DEFAULT)
+                    .await
+                    .is_err()
+                    || stdin
+                        .write_all_future(encoded, glib::Priority::DEFAULT)
+                        .await
+                        .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
         let (event_sender, event_receiver) = async_channel::unbounded();
+        let (failure_sender, failure_receiver) = async_channel::bounded(1);
+        let shutting_down = Rc::new(Cell::new(false));
+        // Assumed until the runner says hello, actions it doesn't know are
+        // dropped on its side
+        let features = Rc::new(Cell::new(features::ALL));
 
         // Async task to receive events from process
         glib::spawn_future_local(glib::clone!(
             #[strong]
             stdout,
+            #[strong]
+            subprocess,
+            #[strong]
+            shutting_down,
+            #[strong]
+            features,
             async move {
+                let mut greeted = false;
                 loop {
                     // Read 4-byte length prefix
                     let len_buf = vec![0u8; 4];
@@ -82,9 +206,32 @@ impl ServoRunner {
                                 .await
                             {
                                 Ok((msg_buf, _, _)) => {
-                                    if let Ok(event) = ServoEvent::decode_from_slice(&msg_buf)
-                                        && event_sender.send(event).await.is_err()
-                                    {
+                                    let event = match ServoEvent::decode_from_slice(&msg_buf) {
+                                        Ok(event) => event,
+                                        Err(err) => {
+                                            warn!("Failed to decode event from runner: {err}");
+                                            continue;
+                                        }
+                                    };
+
+                                    if !greeted {
+                                        match check_hello(&event) {
+                                            Ok(supported) => features.set(supported),
+                                            Err(err) => {
+                                                // The process is of no use, and
+                                                // not worth reporting as
+                                                // terminated
+                                                shutting_down.set(true);
+                                                subprocess.force_exit();
+                                                let _ = failure_sender.send(err).await;
+                                                break;
+                                            }
+                                        }
+                                        greeted = true;
+                                        continue;
+                                    }
+
+                                    if event_sender.send(event).await.is_err() {
                                         break;
                                     }
                                 }
@@ -97,89 +244,397 @@ impl ServoRunner {
             }
         ));
 
-        Self {
-            stdin,
+        let (termination_sender, termination_receiver) = async_channel::bounded(1);
+
+        // Async task to watch for the process going away
+        glib::spawn_future_local(glib::clone!(
+            #[strong]
+            subprocess,
+            #[strong]
+            shutting_down,
+            async move {
+                if subprocess.wait_future().await.is_err() || shutting_down.get() {
+                    return;
+                }
+
+                let termination = if subprocess.has_signaled() {
+                    (TerminationReason::Signaled, subprocess.term_sig())
+                } else if subprocess.exit_status() == PANIC_EXIT_CODE {
+                    (TerminationReason::Crashed, PANIC_EXIT_CODE)
+                } else {
+                    (TerminationReason::Exited, subprocess.exit_status())
+                };
+                let _ = termination_sender.send(termination).await;
+            }
+        ));
+
+        Ok(Self {
+            action_sender,
             event_receiver,
+            termination_receiver,
+            failure_receiver,
+            frame_socket,
+            shutting_down,
+            features,
+            uri_schemes: RefCell::new(HashSet::new()),
+            engine_started: Cell::new(false),
             _subprocess: subprocess,
-        }
+        })
     }
 
     fn send_action(&self, action: ServoAction) {
-        let stdin = self.stdin.clone();
-        glib::spawn_future_local(async move {
-            let encoded = action.encode_to_vec();
-            let len = (encoded.len() as u32).to_le_bytes();
-            let _ = stdin
-                .write_all_future(len.to_vec(), glib::Priority::DEFAULT)
-                .await;
-            let _ = stdin
-                .write_all_future(encoded, glib::Priority::DEFAULT)
-                .await;
+        // Actions reach the runner in order, so this is when it starts
+        // Servo
+        if action.starts_engine() {
+            self.engine_started.set(true);
+        }
+        let _ = self.action_sender.try_send(action);
+    }
+
+    /// Whether the runner supports `feature`, one of the [`features`] bits.
+    pub fn supports(&self, feature: u64) -> bool {
+        self.features.get() & feature == feature
+    }
+
+    /// Turns down the popup `request_id` of `webview_id`, for requests
+    /// arriving after the view went away.
+    pub fn reject_create_request(&self, webview_id: u64, request_id: u64) {
+        self.send_action(ServoAction {
+            webview_id,
+            action: Some(servo_action::Action::CreateResponse(
+                crate::proto_ipc::CreateResponse {
+                    request_id,
+                    accepted: false,
+                },
+            )),
         });
     }
 
+    /// Makes the runner forward requests for `scheme` as `SchemeRequest`
+    /// events, to whichever view made them.
+    ///
+    /// Servo only takes custom schemes when it starts, which is as soon as
+    /// any view of the process loads or gets input, so this fails with
+    /// `NotSupported` afterwards.
+    pub fn register_uri_scheme(&self, scheme: &str) -> Result<(), glib::Error> {
+        if self.uri_schemes.borrow().contains(scheme) {
+            return Ok(());
+        }
+        if self.engine_started.get() {
+            return Err(glib::Error::new(
+                gio::IOErrorEnum::NotSupported,
+                &format!("URI scheme {scheme} has to be registered before the web process starts"),
+            ));
+        }
+
+        self.uri_schemes.borrow_mut().insert(scheme.to_string());
+        self.send_action(ServoAction {
+            webview_id: 0,
+            action: Some(servo_action::Action::RegisterUriScheme(
+                crate::proto_ipc::RegisterUriScheme {
+                    scheme: scheme.to_string(),
+                },
+            )),
+        });
+        Ok(())
+    }
+
+    /// Yields the events of all the views, tagged with their id.
     pub fn event_receiver(&self) -> async_channel::Receiver<ServoEvent> {
         self.event_receiver.clone()
     }
 
-    pub fn load_url(&self, url: &str) {
+    /// Yields the reason and exit code or signal number if the process
+    /// terminates without `shutdown` being called.
+    pub fn termination_receiver(&self) -> async_channel::Receiver<(TerminationReason, i32)> {
+        self.termination_receiver.clone()
+    }
+
+    /// Yields why the process can't be used if it turns out to be
+    /// incompatible, once it said hello. The process is gone by then.
+    pub fn failure_receiver(&self) -> async_channel::Receiver<glib::Error> {
+        self.failure_receiver.clone()
+    }
+
+    pub fn shutdown(&self) {
+        self.shutting_down.set(true);
         self.send_action(ServoAction {
-            action: Some(servo_action::Action::LoadUrl(crate::proto_ipc::LoadUrl {
-                url: url.to_string(),
-            })),
+            webview_id: 0,
+            action: Some(servo_action::Action::Shutdown(true)),
         });
     }
 
-    pub fn reload(&self) {
-        self.send_action(ServoAction {
-            action: Some(servo_action::Action::Reload(true)),
+    pub fn handle_log_message(level: LogLevel, message: &str) {
+        match level {
+            LogLevel::Debug => debug!("{}", message),
+            LogLevel::Info => info!("{}", message),
+            LogLevel::Warn => warn!("{}", message),
+            LogLevel::Error => error!("{}", message),
+        }
+    }
+}
+
+impl Drop for RunnerProcess {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// A scroll over the page, for [`ServoRunner::scroll`].
+#[derive(Debug, Clone, Copy)]
+pub struct ScrollEvent {
+    /// Where the pointer is, the element under it gets scrolled
+    pub x: f64,
+    pub y: f64,
+    pub dx: f64,
+    pub dy: f64,
+    /// Whether the deltas count wheel clicks or pixels
+    pub unit: gdk::ScrollUnit,
+    /// Where the scroll is in a touchpad gesture, which keeps scrolling
+    /// the element it began over
+    pub phase: ScrollPhase,
+    pub modifiers: Modifiers,
+}
+
+/// Where the reply to a snapshot goes, depending on what was asked for.
+enum SnapshotRequest {
+    Png(async_channel::Sender<Result<glib::Bytes, glib::Error>>),
+    Texture(async_channel::Sender<Result<gdk::Texture, glib::Error>>),
+}
+
+/// One web view hosted by a [`RunnerProcess`].
+pub struct ServoRunner {
+    process: Rc<RunnerProcess>,
+    webview_id: u64,
+    frame_buffer: RefCell<FrameBuffer>,
+    next_request_id: Cell<u64>,
+    next_load_id: Cell<u64>,
+    javascript_requests: RefCell<HashMap<u64, async_channel::Sender<Result<JsValue, glib::Error>>>>,
+    snapshot_requests: RefCell<HashMap<u64, SnapshotRequest>>,
+}
+
+impl ServoRunner {
+    /// Creates the view `webview_id` in `process`, with a frame buffer of
+    /// its own.
+    pub fn new(process: Rc<RunnerProcess>, webview_id: u64) -> Result<Self, glib::Error> {
+        let (width, height) = INITIAL_FRAME_SIZE;
+        let frame_buffer = FrameBuffer::new(0, width, height)
+            .and_then(|frame_buffer| {
+                frame_buffer.send(process.frame_socket.as_fd())?;
+                Ok(frame_buffer)
+            })
+            .map_err(|err| {
+                glib::Error::new(
+                    gio::IOErrorEnum::Failed,
+                    &format!("Failed to create frame buffer: {err}"),
+                )
+            })?;
+
+        // The runner takes the frame buffer from the socket when it handles
+        // this action, so it has to be sent right after it
+        process.send_action(ServoAction {
+            webview_id,
+            action: Some(servo_action::Action::CreateWebView(true)),
         });
+
+        Ok(Self {
+            process,
+            webview_id,
+            frame_buffer: RefCell::new(frame_buffer),
+            next_request_id: Cell::new(0),
+            // 0 is for the loads the page starts itself
+            next_load_id: Cell::new(1),
+            javascript_requests: RefCell::new(HashMap::new()),
+            snapshot_requests: RefCell::new(HashMap::new()),
+        })
     }
 
-    pub fn go_back(&self) {
-        self.send_action(ServoAction {
-            action: Some(servo_action::Action::GoBack(true)),
+    pub fn webview_id(&self) -> u64 {
+        self.webview_id
+    }
+
+    /// Whether the process supports `feature`, see
+    /// [`RunnerProcess::supports`].
+    pub fn supports(&self, feature: u64) -> bool {
+        self.process.supports(feature)
+    }
+
+    /// Whether both views are hosted by the same process.
+    pub fn shares_process(&self, other: &ServoRunner) -> bool {
+        Rc::ptr_eq(&self.process, &other.process)
+    }
+
+    fn send_action(&self, action: servo_action::Action) {
+        self.process.send_action(ServoAction {
+            webview_id: self.webview_id,
+            action: Some(action),
         });
     }
 
+    /// Returns the frame in slot `buffer_index` of the frame buffer
+    /// `frame_buffer_id`, which the runner doesn't write to again until the
+    /// returned bytes are freed.
+    ///
+    /// Frames of a buffer replaced since are dropped, the runner forgot
+    /// about it already.
+    pub fn frame_bytes(
+        &self,
+        frame_buffer_id: u32,
+        buffer_index: u32,
+        stride: u32,
+        height: u32,
+    ) -> Option<glib::Bytes> {
+        let frame_buffer = self.frame_buffer.borrow();
+        if frame_buffer.id() != frame_buffer_id {
+            return None;
+        }
+
+        let action_sender = self.process.action_sender.clone();
+        let webview_id = self.webview_id;
+        let release = move || {
+            let _ = action_sender.try_send(ServoAction {
+                webview_id,
+                action: Some(servo_action::Action::ReleaseFrame(
+                    crate::proto_ipc::ReleaseFrame {
+                        buffer_index,
+                        frame_buffer_id,
+                    },
+                )),
+            });
+        };
+        frame_buffer
+            .read_frame(buffer_index, stride, height, release)
+            .inspect_err(|err| warn!("Failed to read frame: {err}"))
+            .ok()
+    }
+
+    /// Gives the slot `buffer_index` of the frame buffer `frame_buffer_id`
+    /// back to the runner without reading it.
+    pub fn release_frame(&self, frame_buffer_id: u32, buffer_index: u32) {
+        if self.frame_buffer.borrow().id() != frame_buffer_id {
+            return;
+        }
+        self.send_action(servo_action::Action::ReleaseFrame(
+            crate::proto_ipc::ReleaseFrame {
+                buffer_index,
+                frame_buffer_id,
+            },
+        ));
+    }
+
+    fn next_request_id(&self) -> u64 {
+        let request_id = self.next_request_id.get();
+        self.next_request_id.set(request_id + 1);
+        request_id
+    }
+
+    fn next_load_id(&self) -> u64 {
+        let load_id = self.next_load_id.get();
+        self.next_load_id.set(load_id + 1);
+        load_id
+    }
+
+    /// Loads `url` and returns the id the `LoadEnd` or `LoadFailed` ending
+    /// the load refers to.
+    pub fn load_url(&self, url: &str) -> u64 {
+        let load_id = self.next_load_id();
+        self.send_action(servo_action::Action::LoadUrl(crate::proto_ipc::LoadUrl {
+            url: url.to_string(),
+            load_id,
+        }));
+        load_id
+    }
+
+    /// Like [`Self::load_url`], for `html`.
+    pub fn load_html(&self, html: &str, base_uri: &str) -> u64 {
+        let load_id = self.next_load_id();
+        self.send_action(servo_action::Action::LoadHtml(crate::proto_ipc::LoadHtml {
+            html: html.to_string(),
+            base_uri: base_uri.to_string(),
+            load_id,
+        }));
+        load_id
+    }
+
+    /// Like [`Self::load_url`], for `data`.
+    pub fn load_bytes(&self, data: &[u8], mime_type: &str, encoding: &str, base_uri: &str) -> u64 {
+        let load_id = self.next_load_id();
+        self.send_action(servo_action::Action::LoadBytes(
+            crate::proto_ipc::LoadBytes {
+                data: data.to_vec(),
+                mime_type: mime_type.to_string(),
+                encoding: encoding.to_string(),
+                base_uri: base_uri.to_string(),
+                load_id,
+            },
+        ));
+        load_id
+    }
+
+    pub fn reload(&self) {
+        self.send_action(servo_action::Action::Reload(true));
+    }
+
+    pub fn go_back(&self) {
+        self.send_action(servo_action::Action::GoBack(true));
+    }
+
     pub fn go_forward(&self) {
-        self.send_action(ServoAction {
-            action: Some(servo_action::Action::GoForward(true)),
-        });
+        self.send_action(servo_action::Action::GoForward(true));
     }
 
+    /// Resizes the view, sending it a frame buffer for the new size first
+    /// if the current one doesn't fit it.
     pub fn resize(&self, width: u32, height: u32) {
-        self.send_action(ServoAction {
-            action: Some(servo_action::Action::Resize(crate::proto_ipc::Resize {
-                width,
-                height,
-            })),
-        });
+        let mut frame_buffer = self.frame_buffer.borrow_mut();
+        if !frame_buffer.fits(width, height) {
+            // The runner takes the frame buffer from the socket when it
+            // handles the resize, so it has to be sent right before it
+            match FrameBuffer::new(frame_buffer.id().wrapping_add(1), width, height).and_then(
+                |resized| {
+                    resized.send(self.process.frame_socket.as_fd())?;
+                    Ok(resized)
+                },
+            ) {
+                Ok(resized) => *frame_buffer = resized,
+                Err(err) => warn!("Failed to create frame buffer for {width}x{height}: {err}"),
+            }
+        }
+
+        self.send_action(servo_action::Action::Resize(crate::proto_ipc::Resize {
+            width,
+            height,
+            frame_buffer_id: frame_buffer.id(),
+        }));
     }
 
     pub fn motion(&self, x: f64, y: f64) {
-        self.send_action(ServoAction {
-            action: Some(servo_action::Action::Motion(crate::proto_ipc::Motion {
-                x,
-                y,
-            })),
-        });
+        self.send_action(servo_action::Action::Motion(crate::proto_ipc::Motion {
+            x,
+            y,
+        }));
     }
 
-    pub fn button_press(&self, button: u32, x: f64, y: f64) {
-        self.send_action(ServoAction {
-            action: Some(servo_action::Action::ButtonPress(
-                crate::proto_ipc::ButtonPress { button, x, y },
-            )),
-        });
+    pub fn button_press(&self, button: u32, x: f64, y: f64, modifiers: Modifiers) {
+        self.send_action(servo_action::Action::ButtonPress(
+            crate::proto_ipc::ButtonPress {
+                button,
+                x,
+                y,
+                modifiers: modifiers.bits(),
+            },
+        ));
     }
 
-    pub fn button_release(&self, button: u32, x: f64, y: f64) {
-        self.send_action(ServoAction {
-            action: Some(servo_action::Action::ButtonRelease(
-                crate::proto_ipc::ButtonRelease { button, x, y },
-            )),
-        });
+    pub fn button_release(&self, button: u32, x: f64, y: f64, modifiers: Modifiers) {
+        self.send_action(servo_action::Action::ButtonRelease(
+            crate::proto_ipc::ButtonRelease {
+                button,
+                x,
+                y,
+                modifiers: modifiers.bits(),
+            },
+        ));
     }
 
     fn convert_location(location: KeyLocation) -> crate::proto_ipc::Location {
@@ -191,116 +646,390 @@ impl ServoRunner {
         }
     }
 
-    pub fn key_press(
-        &self,
-        key: String,
-        is_character: bool,
-        location: KeyLocation,
-        key_code: u32,
-        modifiers: u32,
-    ) {
-        let key_type = if is_character {
+    fn key_type(event: &KeyEvent) -> crate::proto_ipc::KeyType {
+        if event.is_character {
             crate::proto_ipc::KeyType::Character
         } else {
             crate::proto_ipc::KeyType::Named
+        }
+    }
+
+    pub fn key_press(&self, event: &KeyEvent) {
+        self.send_action(servo_action::Action::KeyPress(crate::proto_ipc::KeyPress {
+            key: event.key.clone(),
+            key_type: Self::key_type(event) as i32,
+            location: Self::convert_location(event.location.clone()) as i32,
+            key_code: event.key_code,
+            modifiers: event.modifiers.bits(),
+            code: event.code.to_string(),
+            repeat: event.repeat,
+            is_composing: event.is_composing,
+        }));
+    }
+
+    pub fn key_release(&self, event: &KeyEvent) {
+        self.send_action(servo_action::Action::KeyRelease(
+            crate::proto_ipc::KeyRelease {
+                key: event.key.clone(),
+                key_type: Self::key_type(event) as i32,
+                location: Self::convert_location(event.location.clone()) as i32,
+                key_code: event.key_code,
+                modifiers: event.modifiers.bits(),
+                code: event.code.to_string(),
+                repeat: event.repeat,
+                is_composing: event.is_composing,
+            },
+        ));
+    }
+
+    /// Updates the text being composed by the input method.
+    pub fn ime_preedit(&self, text: &str) {
+        self.send_action(servo_action::Action::ImePreedit(
+            crate::proto_ipc::ImePreedit {
+                text: text.to_string(),
+            },
+        ));
+    }
+
+    pub fn ime_commit(&self, text: &str) {
+        self.send_action(servo_action::Action::ImeCommit(
+            crate::proto_ipc::ImeCommit {
+                text: text.to_string(),
+            },
+        ));
+    }
+
+    /// Deletes `length` characters starting `offset` characters away from
+    /// the cursor of the focused field.
+    pub fn ime_delete_surrounding(&self, offset: i32, length: u32) {
+        self.send_action(servo_action::Action::ImeDeleteSurrounding(
+            crate::proto_ipc::ImeDeleteSurrounding { offset, length },
+        ));
+    }
+
+    pub fn scroll(&self, event: &ScrollEvent) {
+        let unit = match event.unit {
+            gdk::ScrollUnit::Surface => crate::proto_ipc::ScrollUnit::Surface,
+            _ => crate::proto_ipc::ScrollUnit::Wheel,
         };
-        self.send_action(ServoAction {
-            action: Some(servo_action::Action::KeyPress(crate::proto_ipc::KeyPress {
-                key,
-                key_type: key_type as i32,
-                location: Self::convert_location(location) as i32,
-                key_code,
-                modifiers,
-            })),
-        });
+        self.send_action(servo_action::Action::Scroll(crate::proto_ipc::Scroll {
+            dx: event.dx,
+            dy: event.dy,
+            modifiers: event.modifiers.bits(),
+            x: event.x,
+            y: event.y,
+            unit: unit as i32,
+            phase: event.phase as i32,
+        }));
     }
 
-    pub fn key_release(
+    pub fn touch_begin(&self, id: u32, x: f64, y: f64) {
+        self.send_action(servo_action::Action::TouchBegin(
+            crate::proto_ipc::TouchBegin { x, y, id },
+        ));
+    }
+
+    pub fn touch_update(&self, id: u32, x: f64, y: f64) {
+        self.send_action(servo_action::Action::TouchUpdate(
+            crate::proto_ipc::TouchUpdate { x, y, id },
+        ));
+    }
+
+    pub fn touch_end(&self, id: u32, x: f64, y: f64) {
+        self.send_action(servo_action::Action::TouchEnd(crate::proto_ipc::TouchEnd {
+            x,
+            y,
+            id,
+        }));
+    }
+
+    pub fn touch_cancel(&self, id: u32, x: f64, y: f64) {
+        self.send_action(servo_action::Action::TouchCancel(
+            crate::proto_ipc::TouchCancel { x, y, id },
+        ));
+    }
+
+    /// Evaluates `script` in the current page. The result is delivered
+    /// through the returned channel once `javascript_result` is called
+    /// with the matching reply.
+    pub fn evaluate_javascript(
         &self,
-        key: String,
-        is_character: bool,
-        location: KeyLocation,
-        key_code: u32,
-        modifiers: u32,
-    ) {
-        let key_type = if is_character {
-            crate::proto_ipc::KeyType::Character
+        script: &str,
+    ) -> async_channel::Receiver<Result<JsValue, glib::Error>> {
+        let request_id = self.next_request_id();
+        let (sender, receiver) = async_channel::bounded(1);
+        self.javascript_requests
+            .borrow_mut()
+            .insert(request_id, sender);
+
+        self.send_action(servo_action::Action::EvaluateJavascript(
+            crate::proto_ipc::EvaluateJavaScript {
+                request_id,
+                script: script.to_string(),
+            },
+        ));
+
+        receiver
+    }
+
+    pub fn javascript_result(&self, javascript_result: JavaScriptResult) {
+        let Some(sender) = self
+            .javascript_requests
+            .borrow_mut()
+            .remove(&javascript_result.request_id)
+        else {
+            warn!(
+                "Got a result for unknown script {}",
+                javascript_result.request_id
+            );
+            return;
+        };
+
+        let result = match javascript_result.result {
+            Some(java_script_result::Result::Value(value)) => Ok(JsValue::from(value)),
+            Some(java_script_result::Result::Error(message)) => {
+                Err(glib::Error::new(gio::IOErrorEnum::Failed, &message))
+            }
+            None => Ok(JsValue::Null),
+        };
+        let _ = sender.try_send(result);
+    }
+
+    /// Captures `region` of the page as PNG data. The result is delivered
+    /// through the returned channel once `snapshot_result` is called with
+    /// the matching reply.
+    pub fn snapshot(
+        &self,
+        region: SnapshotRegion,
+        options: SnapshotOptions,
+    ) -> async_channel::Receiver<Result<glib::Bytes, glib::Error>> {
+        let (sender, receiver) = async_channel::bounded(1);
+        match self.request_snapshot(region, options, SnapshotFormat::Png) {
+            Ok(request_id) => {
+                self.snapshot_requests
+                    .borrow_mut()
+                    .insert(request_id, SnapshotRequest::Png(sender));
+            }
+            Err(err) => {
+                let _ = sender.try_send(Err(err));
+            }
+        }
+        receiver
+    }
+
+    /// Captures `region` of the page as a texture, like
+    /// [`ServoRunner::snapshot`] but without encoding the pixels.
+    pub fn snapshot_texture(
+        &self,
+        region: SnapshotRegion,
+        options: SnapshotOptions,
+    ) -> async_channel::Receiver<Result<gdk::Texture, glib::Error>> {
+        let (sender, receiver) = async_channel::bounded(1);
+        match self.request_snapshot(region, options, SnapshotFormat::Rgba) {
+            Ok(request_id) => {
+                self.snapshot_requests
+                    .borrow_mut()
+                    .insert(request_id, SnapshotRequest::Texture(sender));
+            }
+            Err(err) => {
+                let _ = sender.try_send(Err(err));
+            }
+        }
+        receiver
+    }
+
+    /// Asks for a snapshot in `format`, returning the id of the request.
+    fn request_snapshot(
+        &self,
+        region: SnapshotRegion,
+        options: SnapshotOptions,
+        format: SnapshotFormat,
+    ) -> Result<u64, glib::Error> {
+        if !self.supports(features::SNAPSHOTS) {
+            return Err(glib::Error::new(
+                gio::IOErrorEnum::NotSupported,
+                "The web process can't take snapshots",
+            ));
+        }
+        options.validate()?;
+        let request_id = self.next_request_id();
+
+        let (region, rectangle) = match region {
+            SnapshotRegion::Visible => (
+                crate::proto_ipc::SnapshotRegion::Visible,
+                gdk::Rectangle::new(0, 0, 0, 0),
+            ),
+            SnapshotRegion::FullDocument => (
+                crate::proto_ipc::SnapshotRegion::FullDocument,
+                gdk::Rectangle::new(0, 0, 0, 0),
+            ),
+            SnapshotRegion::Rectangle(rectangle) => {
+                (crate::proto_ipc::SnapshotRegion::Rectangle, rectangle)
+            }
+        };
+        self.send_action(servo_action::Action::Snapshot(crate::proto_ipc::Snapshot {
+            request_id,
+            region: region as i32,
+            x: rectangle.x(),
+            y: rectangle.y(),
+            width: rectangle.width(),
+            height: rectangle.height(),
+            scale: options.scale,
+            format: format as i32,
+        }));
+
+        Ok(request_id)
+    }
+
+    pub fn snapshot_result(&self, snapshot_result: SnapshotResult) {
+        let Some(sender) = self
+            .snapshot_requests
+            .borrow_mut()
+            .remove(&snapshot_result.request_id)
+        else {
+            warn!(
+                "Got a result for unknown snapshot {}",
+                snapshot_result.request_id
+            );
+            return;
+        };
+
+        let result = if snapshot_result.error.is_empty() {
+            Ok(glib::Bytes::from_owned(snapshot_result.data))
         } else {
-            crate::proto_ipc::KeyType::Named
+            Err(glib::Error::new(
+                gio::IOErrorEnum::Failed,
+                &snapshot_result.error,
+            ))
         };
-        self.send_action(ServoAction {
-            action: Some(servo_action::Action::KeyRelease(
-                crate::proto_ipc::KeyRelease {
-                    key,
-                    key_type: key_type as i32,
-                    location: Self::convert_location(location) as i32,
-                    key_code,
-                    modifiers,
-                },
-            )),
-        });
+        match sender {
+            SnapshotRequest::Png(sender) => {
+                let _ = sender.try_send(result);
+            }
+            SnapshotRequest::Texture(sender) => {
+                let (width, height) = (snapshot_result.width, snapshot_result.height);
+                let texture = result.and_then(|bytes| {
+                    let stride = width as usize * 4;
+                    if width == 0 || height == 0 || bytes.len() != stride * height as usize {
+                        return Err(glib::Error::new(
+                            gio::IOErrorEnum::InvalidData,
+                            "The snapshot doesn't match its size",
+                        ));
+                    }
+                    let texture = gdk::MemoryTexture::new(
+                        width as i32,
+                        height as i32,
+                        gdk::MemoryFormat::R8g8b8a8,
+                        &bytes,
+                        stride,
+                    );
+                    Ok(texture.upcast())
+                });
+                let _ = sender.try_send(texture);
+            }
+        }
     }
 
-    pub fn scroll(&self, dx: f64, dy: f64) {
-        self.send_action(ServoAction {
-            action: Some(servo_action::Action::Scroll(crate::proto_ipc::Scroll {
-                dx,
-                dy,
-            })),
-        });
+    /// Registers `scheme` in the process, see
+    /// [`RunnerProcess::register_uri_scheme`].
+    pub fn register_uri_scheme(&self, scheme: &str) -> Result<(), glib::Error> {
+        self.process.register_uri_scheme(scheme)
     }
 
-    pub fn touch_begin(&self, x: f64, y: f64) {
-        self.send_action(ServoAction {
-            action: Some(servo_action::Action::TouchBegin(
-                crate::proto_ipc::TouchBegin { x, y },
-            )),
-        });
+    pub fn scheme_response(
+        &self,
+        request_id: u64,
+        status: u16,
+        mime_type: &str,
+        data: &[u8],
+        error: &str,
+    ) {
+        self.send_action(servo_action::Action::SchemeResponse(
+            crate::proto_ipc::SchemeResponse {
+                request_id,
+                status: status as u32,
+                mime_type: mime_type.to_string(),
+                data: data.to_vec(),
+                error: error.to_string(),
+            },
+        ));
     }
 
-    pub fn touch_update(&self, x: f64, y: f64) {
-        self.send_action(ServoAction {
-            action: Some(servo_action::Action::TouchUpdate(
-                crate::proto_ipc::TouchUpdate { x, y },
-            )),
-        });
+    pub fn navigation_decision(&self, request_id: u64, allow: bool) {
+        self.send_action(servo_action::Action::NavigationDecision(
+            crate::proto_ipc::NavigationDecision { request_id, allow },
+        ));
     }
 
-    pub fn touch_end(&self, x: f64, y: f64) {
-        self.send_action(ServoAction {
-            action: Some(servo_action::Action::TouchEnd(crate::proto_ipc::TouchEnd {
-                x,
-                y,
-            })),
-        });
+    pub fn dialog_response(&self, request_id: u64, confirmed: bool, text: &str) {
+        self.send_action(servo_action::Action::DialogResponse(
+            crate::proto_ipc::DialogResponse {
+                request_id,
+                confirmed,
+                text: text.to_string(),
+            },
+        ));
     }
 
-    pub fn touch_cancel(&self, x: f64, y: f64) {
-        self.send_action(ServoAction {
-            action: Some(servo_action::Action::TouchCancel(
-                crate::proto_ipc::TouchCancel { x, y },
-            )),
-        });
+    pub fn file_chooser_response(&self, request_id: u64, paths: &[PathBuf]) {
+        self.send_action(servo_action::Action::FileChooserResponse(
+            crate::proto_ipc::FileChooserResponse {
+                request_id,
+                paths: paths
+                    .iter()
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .collect(),
+            },
+        ));
     }
 
-    pub fn shutdown(&self) {
-        self.send_action(ServoAction {
-            action: Some(servo_action::Action::Shutdown(true)),
-        });
+    /// Starts downloading `url` and returns the id the download's events
+    /// refer to.
+    pub fn start_download(&self, url: &str) -> u64 {
+        let download_id = self.next_request_id();
+        self.send_action(servo_action::Action::StartDownload(
+            crate::proto_ipc::StartDownload {
+                download_id,
+                url: url.to_string(),
+            },
+        ));
+        download_id
     }
 
-    pub fn handle_log_message(&self, level: LogLevel, message: &str) {
-        match level {
-            LogLevel::Debug => debug!("{}", message),
-            LogLevel::Info => info!("{}", message),
-            LogLevel::Warn => warn!("{}", message),
-            LogLevel::Error => error!("{}", message),
-        }
+    pub fn download_destination(&self, download_id: u64, path: &str) {
+        self.send_action(servo_action::Action::DownloadDestination(
+            crate::proto_ipc::DownloadDestination {
+                download_id,
+                path: path.to_string(),
+            },
+        ));
+    }
+
+    pub fn cancel_download(&self, download_id: u64) {
+        self.send_action(servo_action::Action::CancelDownload(
+            crate::proto_ipc::CancelDownload { download_id },
+        ));
+    }
+
+    /// Answers a `CreateRequest`. When `accepted`, this view replaces what
+    /// it showed with the web view the page opened.
+    pub fn create_response(&self, request_id: u64, accepted: bool) {
+        self.send_action(servo_action::Action::CreateResponse(
+            crate::proto_ipc::CreateResponse {
+                request_id,
+                accepted,
+            },
+        ));
+    }
+
+    /// Closes the view, which also happens when dropping it.
+    pub fn close(&self) {
+        self.send_action(servo_action::Action::CloseWebView(true));
     }
 }
 
 impl Drop for ServoRunner {
     fn drop(&mut self) {
-        self.shutdown();
+        self.close();
     }
 }
@@ -0,0 +1,59 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use gtk::{gdk, gio, glib};
+
+/// The part of the page captured by [`crate::WebView::snapshot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotRegion {
+    /// What the web view currently shows.
+    Visible,
+    /// The whole scrollable document, rendered in one go.
+    FullDocument,
+    /// A rectangle of the visible part, in widget coordinates.
+    Rectangle(gdk::Rectangle),
+}
+
+/// How [`crate::WebView::snapshot`] renders the page.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnapshotOptions {
+    /// Factor the captured pixels are scaled by, smaller than 1 for
+    /// thumbnails.
+    pub scale: f64,
+}
+
+impl Default for SnapshotOptions {
+    fn default() -> Self {
+        Self { scale: 1.0 }
+    }
+}
+
+impl SnapshotOptions {
+    /// Fails with `InvalidArgument` unless the scale is a positive number.
+    pub(crate) fn validate(&self) -> Result<(), glib::Error> {
+        if !self.scale.is_finite() || self.scale <= 0.0 {
+            return Err(glib::Error::new(
+                gio::IOErrorEnum::InvalidArgument,
+                "The snapshot scale has to be a positive number",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_has_to_be_positive() {
+        for scale in [0.5, 1.0, 2.0] {
+            assert!(SnapshotOptions { scale }.validate().is_ok(), "{scale}");
+        }
+        for scale in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let err = SnapshotOptions { scale }.validate().unwrap_err();
+            assert!(err.matches(gio::IOErrorEnum::InvalidArgument), "{scale}");
+        }
+    }
+}
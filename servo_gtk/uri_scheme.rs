@@ -0,0 +1,99 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::web_view::WebView;
+use gio::prelude::*;
+
+/// A request for a URI with a custom scheme registered through
+/// [`WebView::register_uri_scheme`].
+///
+/// The handler replies by calling one of the `finish` methods. A request
+/// that gets dropped without a reply fails the load.
+pub struct UriSchemeRequest {
+    request_id: u64,
+    uri: String,
+    method: String,
+    web_view: glib::WeakRef<WebView>,
+    finished: bool,
+}
+
+impl UriSchemeRequest {
+    pub(crate) fn new(request_id: u64, uri: String, method: String, web_view: &WebView) -> Self {
+        Self {
+            request_id,
+            uri,
+            method,
+            web_view: web_view.downgrade(),
+            finished: false,
+        }
+    }
+
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    pub fn scheme(&self) -> &str {
+        self.uri.split(':').next().unwrap_or_default()
+    }
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn web_view(&self) -> Option<WebView> {
+        self.web_view.upgrade()
+    }
+
+    fn reply(&mut self, status: u16, mime_type: &str, data: &[u8], error: &str) {
+        self.finished = true;
+        if let Some(web_view) = self.web_view.upgrade() {
+            web_view.finish_uri_scheme_request(self.request_id, status, mime_type, data, error);
+        }
+    }
+
+    /// Replies with `bytes` as the body of the response.
+    pub fn finish(mut self, status: u16, mime_type: &str, bytes: &glib::Bytes) {
+        self.reply(status, mime_type, bytes, "");
+    }
+
+    /// Replies with the whole content of `stream` as the body of the
+    /// response, once it has been read.
+    pub fn finish_stream(
+        mut self,
+        status: u16,
+        mime_type: &str,
+        stream: &impl IsA<gio::InputStream>,
+    ) {
+        let mime_type = mime_type.to_string();
+        let stream: gio::InputStream = stream.clone().upcast();
+        glib::spawn_future_local(async move {
+            let output = gio::MemoryOutputStream::new_resizable();
+            match output
+                .splice_future(
+                    &stream,
+                    gio::OutputStreamSpliceFlags::CLOSE_SOURCE
+                        | gio::OutputStreamSpliceFlags::CLOSE_TARGET,
+                    glib::Priority::DEFAULT,
+                )
+                .await
+            {
+                Ok(_) => self.reply(status, &mime_type, &output.steal_as_bytes(), ""),
+                Err(err) => self.finish_error(&err.to_string()),
+            }
+        });
+    }
+
+    /// Fails the load with `message`.
+    pub fn finish_error(mut self, message: &str) {
+        self.reply(0, "", &[], message);
+    }
+}
+
+impl Drop for UriSchemeRequest {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.reply(0, "", &[], "The request was not handled");
+        }
+    }
+}
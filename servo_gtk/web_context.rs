@@ -0,0 +1,257 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::proto_ipc::{ServoEvent, features, servo_event};
+use crate::servo_runner::{RunnerProcess, ServoRunner};
+use crate::web_view::WebView;
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::rc::{Rc, Weak};
+
+/// Environment variable overriding the servo-runner executable, mostly
+/// useful during development.
+pub const RUNNER_PATH_ENV: &str = "SERVO_GTK_RUNNER_PATH";
+
+const RUNNER_NAME: &str = "servo-runner";
+
+type Views = Rc<RefCell<HashMap<u64, glib::WeakRef<WebView>>>>;
+
+/// The runner process the views of a context currently share.
+pub struct SharedRunner {
+    process: Weak<RunnerProcess>,
+    views: Views,
+}
+
+mod imp {
+    use super::*;
+
+    #[derive(Default, glib::Properties)]
+    #[properties(wrapper_type = super::WebContext)]
+    pub struct WebContext {
+        /// Path of the servo-runner executable
+        #[property(get, set, nullable)]
+        pub runner_path: RefCell<Option<String>>,
+        pub runner: RefCell<Option<SharedRunner>>,
+        pub next_webview_id: Cell<u64>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for WebContext {
+        const NAME: &'static str = "WebContext";
+        type Type = super::WebContext;
+        type ParentType = glib::Object;
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for WebContext {}
+}
+
+glib::wrapper! {
+    /// Settings and the runner process shared by the web views using it.
+    pub struct WebContext(ObjectSubclass<imp::WebContext>);
+}
+
+#[allow(clippy::new_without_default)]
+impl WebContext {
+    pub fn new() -> Self {
+        glib::Object::builder().build()
+    }
+
+    /// Returns the context used by web views that weren't given one.
+    pub fn default_context() -> Self {
+        thread_local! {
+            static DEFAULT_CONTEXT: WebContext = WebContext::new();
+        }
+        DEFAULT_CONTEXT.with(|context| context.clone())
+    }
+
+    /// Finds the servo-runner executable to spawn.
+    ///
+    /// `SERVO_GTK_RUNNER_PATH` takes precedence over the `runner-path`
+    /// property. Otherwise the runner is looked up next to the current
+    /// executable, its parent directory (where cargo puts binaries when
+    /// running examples) and `../libexec`.
+    pub fn find_runner(&self) -> Result<PathBuf, glib::Error> {
+        if let Some(path) = env::var_os(RUNNER_PATH_ENV) {
+            return Ok(PathBuf::from(path));
+        }
+
+        if let Some(path) = self.runner_path() {
+            return Ok(PathBuf::from(path));
+        }
+
+        let exe = env::current_exe().map_err(|err| {
+            glib::Error::new(
+                gio::IOErrorEnum::Failed,
+                &format!("Failed to find the current executable: {err}"),
+            )
+        })?;
+        let exe_dir = exe.parent().unwrap_or(&exe);
+
+        [
+            exe_dir.join(RUNNER_NAME),
+            exe_dir.join("..").join(RUNNER_NAME),
+            exe_dir.join("..").join("libexec").join(RUNNER_NAME),
+        ]
+        .into_iter()
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            glib::Error::new(
+                gio::IOErrorEnum::NotFound,
+                &format!("Could not find {RUNNER_NAME}, set {RUNNER_PATH_ENV} to its location"),
+            )
+        })
+    }
+
+    /// Creates the runner side of `web_view`, spawning the runner process
+    /// if no other view of the context is running, or if it can't host
+    /// more than one.
+    pub(crate) fn create_view(&self, web_view: &WebView) -> Result<ServoRunner, glib::Error> {
+        let imp = self.imp();
+
+        let shared = imp.runner.borrow().as_ref().and_then(|runner| {
+            runner
+                .process
+                .upgrade()
+                .filter(|process| process.supports(features::MULTIPLE_WEB_VIEWS))
+                .map(|process| (process, runner.views.clone()))
+        });
+        let (process, views) = match shared {
+            Some(shared) => shared,
+            None => {
+                let process = Rc::new(RunnerProcess::new(&self.find_runner()?)?);
+                let views = Views::default();
+                self.watch_process(&process, &views);
+                imp.runner.replace(Some(SharedRunner {
+                    process: Rc::downgrade(&process),
+                    views: views.clone(),
+                }));
+                (process, views)
+            }
+        };
+
+        // Ids start at 1, 0 being the runner itself
+        let webview_id = imp.next_webview_id.get() + 1;
+        imp.next_webview_id.set(webview_id);
+
+        let servo_runner = ServoRunner::new(process, webview_id)?;
+        let mut views = views.borrow_mut();
+        views.retain(|_, view| view.upgrade().is_some());
+        views.insert(webview_id, web_view.downgrade());
+        Ok(servo_runner)
+    }
+
+    /// Dispatches the events of `process` to the views they come from.
+    fn watch_process(&self, process: &Rc<RunnerProcess>, views: &Views) {
+        let event_receiver = process.event_receiver();
+        let termination_receiver = process.termination_receiver();
+        let failure_receiver = process.failure_receiver();
+
+        let dispatch_views = views.clone();
+        let dispatch_process = Rc::downgrade(process);
+        glib::spawn_future_local(async move {
+            while let Ok(event) = event_receiver.recv().await {
+                dispatch_event(&dispatch_views, &dispatch_process, event);
+            }
+        });
+
+        let context_weak = self.downgrade();
+        let process_weak = Rc::downgrade(process);
+        let termination_views = views.clone();
+        glib::spawn_future_local(async move {
+            let Ok((reason, code)) = termination_receiver.recv().await else {
+                return;
+            };
+
+            for web_view in forget_process(&context_weak, &process_weak, &termination_views) {
+                web_view.process_runner_terminated(reason, code);
+            }
+        });
+
+        let context_weak = self.downgrade();
+        let process_weak = Rc::downgrade(process);
+        let views = views.clone();
+        glib::spawn_future_local(async move {
+            let Ok(err) = failure_receiver.recv().await else {
+                return;
+            };
+
+            // Restarting would only spawn the same runner again, so the
+            // views stay in their error state
+            for web_view in forget_process(&context_weak, &process_weak, &views) {
+                web_view.process_runner_failed(err.clone());
+            }
+        });
+    }
+}
+
+/// Stops handing out `process`, which went away, and returns the views it
+/// was running.
+fn forget_process(
+    context_weak: &glib::WeakRef<WebContext>,
+    process_weak: &Weak<RunnerProcess>,
+    views: &Views,
+) -> Vec<WebView> {
+    if let Some(context) = context_weak.upgrade() {
+        let mut runner = context.imp().runner.borrow_mut();
+        if runner
+            .as_ref()
+            .is_some_and(|runner| runner.process.ptr_eq(process_weak))
+        {
+            runner.take();
+        }
+    }
+
+    // Views restarting the runner go through `create_view`, so the map
+    // can't stay borrowed
+    views
+        .borrow_mut()
+        .drain()
+        .filter_map(|(_, view)| view.upgrade())
+        .collect()
+}
+
+fn dispatch_event(views: &Views, process: &Weak<RunnerProcess>, event: ServoEvent) {
+    if let Some(servo_event::Event::LogMessage(log_message)) = &event.event {
+        RunnerProcess::handle_log_message(log_message.level.into(), &log_message.message);
+        return;
+    }
+
+    let web_view = {
+        let views = views.borrow();
+        match views.get(&event.webview_id) {
+            Some(view) => view.upgrade(),
+            // Scheme requests Servo couldn't attribute to a view go to the
+            // first one able to handle them
+            None => match &event.event {
+                Some(servo_event::Event::SchemeRequest(scheme_request)) => {
+                    let scheme = scheme_request.uri.split(':').next().unwrap_or_default();
+                    views
+                        .values()
+                        .filter_map(|view| view.upgrade())
+                        .find(|view| view.handles_uri_scheme(scheme))
+                }
+                _ => None,
+            },
+        }
+    };
+
+    // The view may already be gone while the runner catches up
+    match web_view {
+        Some(web_view) => web_view.process_servo_event(event),
+        // Nobody is left to show the popup, don't leave it open
+        None => {
+            if let Some(servo_event::Event::CreateRequest(create_request)) = event.event
+                && let Some(process) = process.upgrade()
+            {
+                process.reject_create_request(event.webview_id, create_request.request_id);
+            }
+        }
+    }
+}
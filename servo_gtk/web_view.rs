@@ -2,27 +2,122 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::key_tables::KeyTables;
-use crate::proto_ipc::{ServoEvent, servo_event};
-use crate::servo_runner::{LogLevel, ServoRunner};
-use glib::info;
+use crate::download::Download;
+use crate::file_chooser_request::FileChooserRequest;
+use crate::hit_test_result::HitTestResult;
+use crate::js_value::JsValue;
+use crate::key_tables::{self, KeyEvent, KeyTables};
+use crate::modifiers::{self, LockState};
+use crate::navigation_policy::{NavigationPolicyDecision, NavigationType};
+use crate::proto_ipc::{ImeState, ScrollPhase, ServoEvent, features, servo_event};
+use crate::script_dialog::{ScriptDialog, ScriptDialogType};
+use crate::servo_runner::{LogLevel, RunnerProcess, ScrollEvent, ServoRunner, TerminationReason};
+use crate::snapshot::{SnapshotOptions, SnapshotRegion};
+use crate::uri_scheme::UriSchemeRequest;
+use crate::web_context::WebContext;
+use glib::subclass::Signal;
 use glib::translate::*;
+use glib::{info, warn};
 use gtk::gdk;
 use gtk::prelude::*;
 use gtk::{glib, subclass::prelude::*};
-use image::RgbaImage;
-use std::cell::RefCell;
+use keyboard_types::Modifiers;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+type UriSchemeHandler = Rc<dyn Fn(UriSchemeRequest)>;
 
 const G_LOG_DOMAIN: &str = "ServoGtk";
 
+/// How fast kinetic scrolling slows down, the time in seconds it takes to
+/// lose about two thirds of its speed.
+const KINETIC_TIME_CONSTANT: f64 = 0.325;
+
+/// The speed in pixels per second below which kinetic scrolling stops.
+const KINETIC_MIN_VELOCITY: f64 = 20.0;
+
+/// What the input method did with a key event.
+enum InputMethodFilter {
+    /// It left the event alone
+    Ignored,
+    /// It took the event to compose text
+    Composing,
+    /// It took the event and committed the text right away, outside of any
+    /// composition
+    Committed(String),
+}
+
 mod imp {
     use super::*;
 
-    #[derive(Default)]
+    #[derive(Default, glib::Properties)]
+    #[properties(wrapper_type = super::WebView)]
     pub struct WebView {
         pub servo_runner: RefCell<Option<ServoRunner>>,
-        pub memory_texture: RefCell<Option<gdk::MemoryTexture>>,
+        pub memory_texture: RefCell<Option<gdk::Texture>>,
         pub key_tables: KeyTables,
+        /// The keycodes held down, to tell GTK's repeated presses apart
+        pub pressed_keys: RefCell<HashSet<u32>>,
+        /// Whether a dead key or the input method started a composition
+        /// that isn't over yet
+        pub composing: Cell<bool>,
+        /// Whether AltGr is held down, which GDK has no modifier mask for
+        pub alt_graph: Cell<bool>,
+        /// The ids of the touch points in progress, by the address of
+        /// their `gdk::EventSequence`
+        pub touch_ids: RefCell<HashMap<usize, u32>>,
+        /// Where the pointer was at the last scroll
+        pub scroll_position: Cell<(f64, f64)>,
+        /// Whether a touchpad scroll gesture is in progress
+        pub scrolling: Cell<bool>,
+        /// The tick callback moving the page after a touchpad gesture
+        pub kinetic_scroll: RefCell<Option<gtk::TickCallbackId>>,
+        pub im_context: gtk::IMMulticontext,
+        /// Whether a text field of the page has the focus
+        pub ime_active: Cell<bool>,
+        /// Whether the page got the start of a composition and not its end
+        pub ime_composing: Cell<bool>,
+        /// The text committed outside of a composition while the input
+        /// method filters a key press, sent with the key
+        pub ime_key_commit: RefCell<Option<String>>,
+        /// The text around the cursor of the focused field, and the byte
+        /// index of the cursor in it
+        pub ime_surrounding: RefCell<(String, i32)>,
+        pub placeholder: RefCell<Option<String>>,
+        pub uri_schemes: RefCell<HashMap<String, UriSchemeHandler>>,
+        pub context_menu: RefCell<Option<gtk::PopoverMenu>>,
+        pub downloads: RefCell<HashMap<u64, Download>>,
+        /// The context the runner process is spawned from
+        #[property(get, set, construct_only)]
+        pub web_context: RefCell<Option<WebContext>>,
+        /// The web view whose page opened this one, if any
+        #[property(get)]
+        pub opener: glib::WeakRef<super::WebView>,
+        /// Whether to respawn the runner process when it terminates unexpectedly
+        #[property(get, set)]
+        pub restart_on_crash: Cell<bool>,
+        /// The title of the current page
+        #[property(get)]
+        pub title: RefCell<Option<String>>,
+        /// The URI of the current page
+        #[property(get)]
+        pub uri: RefCell<Option<String>>,
+        /// Whether a page is being loaded
+        #[property(get)]
+        pub is_loading: Cell<bool>,
+        /// Rough estimate of how far the current load is, between 0 and 1
+        #[property(get, minimum = 0.0, maximum = 1.0)]
+        pub estimated_load_progress: Cell<f64>,
+        /// Whether there is a previous page in the history
+        #[property(get)]
+        pub can_go_back: Cell<bool>,
+        /// Whether there is a next page in the history
+        #[property(get)]
+        pub can_go_forward: Cell<bool>,
     }
 
     #[glib::object_subclass]
@@ -30,27 +125,155 @@ mod imp {
         const NAME: &'static str = "WebView";
         type Type = super::WebView;
         type ParentType = gtk::Widget;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.install_action("web-view.go-back", None, |obj, _, _| obj.go_back());
+            klass.install_action("web-view.go-forward", None, |obj, _, _| obj.go_forward());
+            klass.install_action("web-view.reload", None, |obj, _, _| obj.reload());
+            klass.install_action(
+                "web-view.copy",
+                Some(glib::VariantTy::STRING),
+                |obj, _, text| {
+                    if let Some(text) = text.and_then(|text| text.str()) {
+                        obj.clipboard().set_text(text);
+                    }
+                },
+            );
+            klass.install_action(
+                "web-view.copy-link",
+                Some(glib::VariantTy::STRING),
+                |obj, _, uri| {
+                    if let Some(uri) = uri.and_then(|uri| uri.str()) {
+                        obj.clipboard().set_text(uri);
+                    }
+                },
+            );
+            klass.install_action(
+                "web-view.open-image",
+                Some(glib::VariantTy::STRING),
+                |obj, _, uri| {
+                    if let Some(uri) = uri.and_then(|uri| uri.str()) {
+                        obj.load_url(uri);
+                    }
+                },
+            );
+        }
     }
 
+    #[glib::derived_properties]
     impl ObjectImpl for WebView {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            SIGNALS.get_or_init(|| {
+                vec![
+                    Signal::builder("web-process-terminated")
+                        .param_types([TerminationReason::static_type(), i32::static_type()])
+                        .build(),
+                    Signal::builder("web-process-failed")
+                        .param_types([glib::Error::static_type()])
+                        .build(),
+                    // Handlers return true when they took care of the
+                    // decision, the navigation is allowed otherwise
+                    Signal::builder("decide-policy")
+                        .param_types([NavigationPolicyDecision::static_type()])
+                        .return_type::<bool>()
+                        .accumulator(|_hint, _accumulated, value| {
+                            if value.get::<bool>().unwrap_or(false) {
+                                ControlFlow::Break(value.clone())
+                            } else {
+                                ControlFlow::Continue(value.clone())
+                            }
+                        })
+                        .build(),
+                    // Handlers return true when they answer the dialog,
+                    // a stock GTK dialog is shown otherwise
+                    Signal::builder("script-dialog")
+                        .param_types([ScriptDialog::static_type()])
+                        .return_type::<bool>()
+                        .accumulator(|_hint, _accumulated, value| {
+                            if value.get::<bool>().unwrap_or(false) {
+                                ControlFlow::Break(value.clone())
+                            } else {
+                                ControlFlow::Continue(value.clone())
+                            }
+                        })
+                        .build(),
+                    // Emitted unless the page cancels its contextmenu event.
+                    // Handlers can edit the menu, and return true when they
+                    // show it themselves or don't want it shown
+                    Signal::builder("context-menu")
+                        .param_types([gio::Menu::static_type(), HitTestResult::static_type()])
+                        .return_type::<bool>()
+                        .accumulator(|_hint, _accumulated, value| {
+                            if value.get::<bool>().unwrap_or(false) {
+                                ControlFlow::Break(value.clone())
+                            } else {
+                                ControlFlow::Continue(value.clone())
+                            }
+                        })
+                        .build(),
+                    // Handlers return true when they answer the request,
+                    // a gtk::FileDialog is shown otherwise
+                    Signal::builder("run-file-chooser")
+                        .param_types([FileChooserRequest::static_type()])
+                        .return_type::<bool>()
+                        .accumulator(|_hint, _accumulated, value| {
+                            if value.get::<bool>().unwrap_or(false) {
+                                ControlFlow::Break(value.clone())
+                            } else {
+                                ControlFlow::Continue(value.clone())
+                            }
+                        })
+                        .build(),
+                    // Handlers return a new web view sharing this one's
+                    // context to show a window opened by the page, which
+                    // is blocked otherwise
+                    Signal::builder("create")
+                        .return_type::<Option<super::WebView>>()
+                        .accumulator(|_hint, _accumulated, value| {
+                            if value
+                                .get::<Option<super::WebView>>()
+                                .ok()
+                                .flatten()
+                                .is_some()
+                            {
+                                ControlFlow::Break(value.clone())
+                            } else {
+                                ControlFlow::Continue(value.clone())
+                            }
+                        })
+                        .build(),
+                    // The page asked to close its window
+                    Signal::builder("close").build(),
+                    Signal::builder("download-started")
+                        .param_types([Download::static_type()])
+                        .build(),
+                    // Handlers get the suggested file name and return true
+                    // when they set the destination, possibly later on.
+                    // The file goes to the download directory otherwise.
+                    Signal::builder("decide-destination")
+                        .param_types([Download::static_type(), String::static_type()])
+                        .return_type::<bool>()
+                        .accumulator(|_hint, _accumulated, value| {
+                            if value.get::<bool>().unwrap_or(false) {
+                                ControlFlow::Break(value.clone())
+                            } else {
+                                ControlFlow::Continue(value.clone())
+                            }
+                        })
+                        .build(),
+                ]
+            })
+        }
+
         fn constructed(&self) {
             self.parent_constructed();
 
-            let servo_runner = ServoRunner::new();
-            let event_receiver = servo_runner.event_receiver();
+            self.obj().start_servo_runner();
 
-            self.servo_runner.replace(Some(servo_runner));
-
-            let obj_weak = self.obj().downgrade();
-            glib::spawn_future_local(async move {
-                while let Ok(event) = event_receiver.recv().await {
-                    if let Some(obj) = obj_weak.upgrade() {
-                        obj.process_servo_event(event);
-                    } else {
-                        break;
-                    }
-                }
-            });
+            let obj = self.obj();
+            obj.action_set_enabled("web-view.go-back", false);
+            obj.action_set_enabled("web-view.go-forward", false);
 
             // Event controllers
             let motion_controller = gtk::EventControllerMotion::new();
@@ -77,28 +300,39 @@ mod imp {
                             gdk::EventType::ButtonPress => {
                                 if let Some(button_event) = event.downcast_ref::<gdk::ButtonEvent>()
                                 {
-                                    servo.button_press(button_event.button(), x, y);
+                                    servo.button_press(
+                                        button_event.button(),
+                                        x,
+                                        y,
+                                        obj.dom_modifiers(event.modifier_state()),
+                                    );
                                 }
+                                obj.stop_kinetic_scroll();
                                 controller.widget().expect("Controller widget").grab_focus();
                             }
                             gdk::EventType::ButtonRelease => {
                                 if let Some(button_event) = event.downcast_ref::<gdk::ButtonEvent>()
                                 {
-                                    servo.button_release(button_event.button(), x, y);
+                                    servo.button_release(
+                                        button_event.button(),
+                                        x,
+                                        y,
+                                        obj.dom_modifiers(event.modifier_state()),
+                                    );
                                 }
                             }
                             gdk::EventType::TouchBegin => {
-                                servo.touch_begin(x, y);
+                                servo.touch_begin(obj.touch_id(event, false), x, y);
                                 controller.widget().expect("Controller widget").grab_focus();
                             }
                             gdk::EventType::TouchUpdate => {
-                                servo.touch_update(x, y);
+                                servo.touch_update(obj.touch_id(event, false), x, y);
                             }
                             gdk::EventType::TouchEnd => {
-                                servo.touch_end(x, y);
+                                servo.touch_end(obj.touch_id(event, true), x, y);
                             }
                             gdk::EventType::TouchCancel => {
-                                servo.touch_cancel(x, y);
+                                servo.touch_cancel(obj.touch_id(event, true), x, y);
                             }
                             _ => {}
                         }
@@ -110,45 +344,205 @@ mod imp {
 
             let key_controller = gtk::EventControllerKey::new();
             let obj_weak = self.obj().downgrade();
-            key_controller.connect_key_pressed(move |_, keyval, keycode, state| {
+            key_controller.connect_key_pressed(move |controller, keyval, keycode, state| {
+                let Some(obj) = obj_weak.upgrade() else {
+                    return glib::Propagation::Proceed;
+                };
+                // The page gets the keys the input method takes too, like
+                // browsers do
+                let filter = obj.filter_input_method_event(controller);
+                let propagation = if matches!(filter, InputMethodFilter::Ignored) {
+                    glib::Propagation::Proceed
+                } else {
+                    glib::Propagation::Stop
+                };
+                let Some(mut event) = obj.key_event(keyval, keycode, state, true) else {
+                    return propagation;
+                };
+                match filter {
+                    InputMethodFilter::Ignored => {}
+                    InputMethodFilter::Composing => {
+                        event.key = "Process".to_string();
+                        event.is_character = false;
+                        event.is_composing = true;
+                    }
+                    InputMethodFilter::Committed(text) => {
+                        event.key = text;
+                        event.is_character = true;
+                    }
+                }
+                if let Some(servo) = obj.imp().servo_runner.borrow().as_ref() {
+                    info!(
+                        "Pressed key {:?} ({}) at location {:?}",
+                        event.key, event.code, event.location
+                    );
+                    servo.key_press(&event);
+                }
+                propagation
+            });
+            let obj_weak = self.obj().downgrade();
+            key_controller.connect_key_released(move |controller, keyval, keycode, state| {
+                let Some(obj) = obj_weak.upgrade() else {
+                    return;
+                };
+                // Every key the page saw pressed gets released
+                obj.filter_input_method_event(controller);
+                if let Some(event) = obj.key_event(keyval, keycode, state, false)
+                    && let Some(servo) = obj.imp().servo_runner.borrow().as_ref()
+                {
+                    servo.key_release(&event);
+                }
+            });
+            self.obj().add_controller(key_controller);
+
+            let focus_controller = gtk::EventControllerFocus::new();
+            let obj_weak = self.obj().downgrade();
+            focus_controller.connect_enter(move |_| {
                 if let Some(obj) = obj_weak.upgrade() {
                     let imp = obj.imp();
-                    if let Some(servo) = imp.servo_runner.borrow().as_ref()
-                        && let Some((key, is_character, location)) =
-                            imp.key_tables.key_from_keyval(keyval.into_glib())
-                    {
-                        info!("Pressed key {key:?} at location {location:?}");
-                        servo.key_press(key, is_character, location, keycode, state.bits());
+                    if imp.ime_active.get() {
+                        imp.im_context.focus_in();
                     }
                 }
-                glib::Propagation::Proceed
             });
+            // Releases don't reach the view once it lost the focus, so
+            // forget about the keys held down then
             let obj_weak = self.obj().downgrade();
-            key_controller.connect_key_released(move |_, keyval, keycode, state| {
+            focus_controller.connect_leave(move |_| {
                 if let Some(obj) = obj_weak.upgrade() {
                     let imp = obj.imp();
-                    if let Some(servo) = imp.servo_runner.borrow().as_ref()
-                        && let Some((key, is_character, location)) =
-                            imp.key_tables.key_from_keyval(keyval.into_glib())
+                    imp.pressed_keys.borrow_mut().clear();
+                    imp.composing.set(false);
+                    imp.alt_graph.set(false);
+                    imp.im_context.focus_out();
+                }
+            });
+            self.obj().add_controller(focus_controller);
+
+            // The input method only gets the keys while a text field has
+            // the focus, see `filter_input_method_event`
+            let im_context = &self.im_context;
+            im_context.set_client_widget(Some(&*self.obj()));
+            let obj_weak = self.obj().downgrade();
+            im_context.connect_commit(move |_, text| {
+                let Some(obj) = obj_weak.upgrade() else {
+                    return;
+                };
+                let imp = obj.imp();
+                if !imp.ime_composing.get()
+                    && let Some(key_commit) = imp.ime_key_commit.borrow_mut().as_mut()
+                {
+                    key_commit.push_str(text);
+                    return;
+                }
+                imp.ime_composing.set(false);
+                if let Some(servo) = imp.servo_runner.borrow().as_ref() {
+                    servo.ime_commit(text);
+                }
+            });
+            let obj_weak = self.obj().downgrade();
+            im_context.connect_preedit_start(move |_| {
+                if let Some(obj) = obj_weak.upgrade() {
+                    obj.imp().composing.set(true);
+                }
+            });
+            let obj_weak = self.obj().downgrade();
+            im_context.connect_preedit_end(move |_| {
+                if let Some(obj) = obj_weak.upgrade() {
+                    obj.imp().composing.set(false);
+                }
+            });
+            let obj_weak = self.obj().downgrade();
+            im_context.connect_preedit_changed(move |im_context| {
+                let Some(obj) = obj_weak.upgrade() else {
+                    return;
+                };
+                let imp = obj.imp();
+                let (text, _, _) = im_context.preedit_string();
+                // The composition starts with the first text composed, and
+                // the page needs to get what was committed before
+                if !imp.ime_composing.get() {
+                    if text.is_empty() {
+                        return;
+                    }
+                    imp.ime_composing.set(true);
+                    let key_commit = imp
+                        .ime_key_commit
+                        .borrow_mut()
+                        .as_mut()
+                        .map(std::mem::take)
+                        .filter(|key_commit| !key_commit.is_empty());
+                    if let Some(key_commit) = key_commit
+                        && let Some(servo) = imp.servo_runner.borrow().as_ref()
                     {
-                        servo.key_release(key, is_character, location, keycode, state.bits());
+                        servo.ime_commit(&key_commit);
                     }
                 }
+                if let Some(servo) = imp.servo_runner.borrow().as_ref() {
+                    servo.ime_preedit(&text);
+                }
+            });
+            let obj_weak = self.obj().downgrade();
+            im_context.connect_retrieve_surrounding(move |im_context| {
+                let Some(obj) = obj_weak.upgrade() else {
+                    return false;
+                };
+                let surrounding = obj.imp().ime_surrounding.borrow();
+                let (text, cursor_index) = &*surrounding;
+                im_context.set_surrounding_with_selection(text, *cursor_index, *cursor_index);
+                true
+            });
+            let obj_weak = self.obj().downgrade();
+            im_context.connect_delete_surrounding(move |_, offset, n_chars| {
+                if let Some(obj) = obj_weak.upgrade()
+                    && let Some(servo) = obj.imp().servo_runner.borrow().as_ref()
+                {
+                    servo.ime_delete_surrounding(offset, n_chars.max(0) as u32);
+                    return true;
+                }
+                false
             });
-            self.obj().add_controller(key_controller);
 
-            let scroll_controller =
-                gtk::EventControllerScroll::new(gtk::EventControllerScrollFlags::BOTH_AXES);
+            let scroll_controller = gtk::EventControllerScroll::new(
+                gtk::EventControllerScrollFlags::BOTH_AXES
+                    | gtk::EventControllerScrollFlags::KINETIC,
+            );
+            let obj_weak = self.obj().downgrade();
+            scroll_controller.connect_scroll_begin(move |controller| {
+                if let Some(obj) = obj_weak.upgrade() {
+                    obj.stop_kinetic_scroll();
+                    obj.imp().scrolling.set(true);
+                    obj.send_scroll(controller, 0.0, 0.0, ScrollPhase::Begin);
+                }
+            });
             let obj_weak = self.obj().downgrade();
-            scroll_controller.connect_scroll(move |_, delta_x, delta_y| {
+            scroll_controller.connect_scroll(move |controller, delta_x, delta_y| {
                 if let Some(obj) = obj_weak.upgrade() {
                     let imp = obj.imp();
-                    if let Some(servo) = imp.servo_runner.borrow().as_ref() {
-                        servo.scroll(delta_x, delta_y);
-                    }
+                    obj.stop_kinetic_scroll();
+                    let phase = if imp.scrolling.get() {
+                        ScrollPhase::Update
+                    } else {
+                        ScrollPhase::Discrete
+                    };
+                    obj.send_scroll(controller, delta_x, delta_y, phase);
                 }
                 glib::Propagation::Stop
             });
+            let obj_weak = self.obj().downgrade();
+            scroll_controller.connect_scroll_end(move |controller| {
+                if let Some(obj) = obj_weak.upgrade() {
+                    obj.imp().scrolling.set(false);
+                    obj.send_scroll(controller, 0.0, 0.0, ScrollPhase::End);
+                }
+            });
+            let obj_weak = self.obj().downgrade();
+            scroll_controller.connect_decelerate(move |controller, velocity_x, velocity_y| {
+                if let Some(obj) = obj_weak.upgrade() {
+                    let modifiers = obj.dom_modifiers(controller.current_event_state());
+                    obj.start_kinetic_scroll(velocity_x, velocity_y, modifiers);
+                }
+            });
             self.obj().add_controller(scroll_controller);
 
             self.obj().set_focusable(true);
@@ -156,14 +550,31 @@ mod imp {
         }
 
         fn dispose(&self) {
-            if let Some(servo) = self.servo_runner.borrow().as_ref() {
-                servo.shutdown();
+            // Closes the view, and the runner process with the last one
+            self.servo_runner.take();
+            self.im_context.set_client_widget(None::<&gtk::Widget>);
+            if let Some(context_menu) = self.context_menu.take() {
+                context_menu.unparent();
             }
         }
     }
 
     impl WidgetImpl for WebView {
         fn snapshot(&self, snapshot: &gtk::Snapshot) {
+            if let Some(placeholder) = self.placeholder.borrow().as_deref() {
+                let obj = self.obj();
+                let layout = obj.create_pango_layout(Some(placeholder));
+                let (text_width, text_height) = layout.pixel_size();
+                snapshot.save();
+                snapshot.translate(&gtk::graphene::Point::new(
+                    (obj.width() - text_width) as f32 / 2.0,
+                    (obj.height() - text_height) as f32 / 2.0,
+                ));
+                snapshot.append_layout(&layout, &obj.color());
+                snapshot.restore();
+                return;
+            }
+
             if let Some(texture) = self.memory_texture.borrow().as_ref() {
                 let bounds = gtk::graphene::Rect::new(
                     0.0,
@@ -179,6 +590,9 @@ mod imp {
             if let Some(servo) = self.servo_runner.borrow().as_ref() {
                 servo.resize(width as u32, height as u32);
             }
+            if let Some(context_menu) = self.context_menu.borrow().as_ref() {
+                context_menu.present();
+            }
         }
     }
 }
@@ -195,6 +609,14 @@ impl WebView {
         glib::Object::builder().build()
     }
 
+    pub fn with_context(web_context: &WebContext) -> Self {
+        glib::Object::builder()
+            .property("web-context", web_context)
+            .build()
+    }
+
+    /// Starts loading `url`. The `uri` property follows once the page
+    /// commits, so it doesn't change if the navigation is ignored or fails.
     pub fn load_url(&self, url: &str) {
         let imp = self.imp();
         if let Some(servo) = imp.servo_runner.borrow().as_ref() {
@@ -202,6 +624,163 @@ impl WebView {
         }
     }
 
+    /// Loads `html` as if it came from `base_uri`, which gives the document
+    /// its origin and location, and resolves its relative URLs. The
+    /// document stays in place of what `base_uri` points to for reloads,
+    /// until the web view navigates elsewhere.
+    pub fn load_html(&self, html: &str, base_uri: Option<&str>) {
+        let imp = self.imp();
+        if let Some(servo) = imp.servo_runner.borrow().as_ref() {
+            servo.load_html(html, base_uri.unwrap_or_default());
+        }
+    }
+
+    /// Loads `bytes` as a document of the given type, which defaults to
+    /// UTF-8 encoded `text/html`, as if it came from `base_uri` like
+    /// [`Self::load_html`].
+    pub fn load_bytes(
+        &self,
+        bytes: &glib::Bytes,
+        mime_type: Option<&str>,
+        encoding: Option<&str>,
+        base_uri: Option<&str>,
+    ) {
+        let imp = self.imp();
+        if let Some(servo) = imp.servo_runner.borrow().as_ref() {
+            servo.load_bytes(
+                bytes,
+                mime_type.unwrap_or("text/html"),
+                encoding.unwrap_or("UTF-8"),
+                base_uri.unwrap_or_default(),
+            );
+        }
+    }
+
+    /// Registers `handler` to serve the URIs with the given scheme.
+    ///
+    /// The handler runs on the main thread. Servo only takes custom schemes
+    /// when it starts, and it is shared by all the web views of the
+    /// context: it starts as soon as any of them loads a page or receives
+    /// input. Registering fails with `NotSupported` afterwards, so register
+    /// schemes right after creating the first web view of the context,
+    /// before loading anything. It fails with `NotConnected` if the web
+    /// process couldn't be started.
+    pub fn register_uri_scheme(
+        &self,
+        scheme: &str,
+        handler: impl Fn(UriSchemeRequest) + 'static,
+    ) -> Result<(), glib::Error> {
+        let imp = self.imp();
+        imp.servo_runner
+            .borrow()
+            .as_ref()
+            .ok_or_else(|| {
+                glib::Error::new(
+                    gio::IOErrorEnum::NotConnected,
+                    "The web process is not running",
+                )
+            })?
+            .register_uri_scheme(scheme)?;
+        imp.uri_schemes
+            .borrow_mut()
+            .insert(scheme.to_string(), Rc::new(handler));
+        Ok(())
+    }
+
+    pub(crate) fn handles_uri_scheme(&self, scheme: &str) -> bool {
+        self.imp().uri_schemes.borrow().contains_key(scheme)
+    }
+
+    pub(crate) fn finish_uri_scheme_request(
+        &self,
+        request_id: u64,
+        status: u16,
+        mime_type: &str,
+        data: &[u8],
+        error: &str,
+    ) {
+        if let Some(servo) = self.imp().servo_runner.borrow().as_ref() {
+            servo.scheme_response(request_id, status, mime_type, data, error);
+        }
+    }
+
+    pub(crate) fn finish_navigation_decision(&self, request_id: u64, allow: bool) {
+        if let Some(servo) = self.imp().servo_runner.borrow().as_ref() {
+            servo.navigation_decision(request_id, allow);
+        }
+    }
+
+    pub(crate) fn finish_script_dialog(&self, request_id: u64, confirmed: bool, text: &str) {
+        if let Some(servo) = self.imp().servo_runner.borrow().as_ref() {
+            servo.dialog_response(request_id, confirmed, text);
+        }
+    }
+
+    pub(crate) fn finish_file_chooser_request(&self, request_id: u64, paths: &[PathBuf]) {
+        if let Some(servo) = self.imp().servo_runner.borrow().as_ref() {
+            servo.file_chooser_response(request_id, paths);
+        }
+    }
+
+    /// Downloads `uri` with the cookies of the view's context, following
+    /// redirects.
+    ///
+    /// Servo doesn't hand the responses of navigations to the embedder, so
+    /// attachments aren't turned into downloads by themselves, and this is
+    /// typically called from a `decide-policy` handler that ignores the
+    /// navigation. The download fails with `NotSupported` if the web process
+    /// can't download.
+    pub fn download_uri(&self, uri: &str) -> Download {
+        let imp = self.imp();
+        let download_id = match imp.servo_runner.borrow().as_ref() {
+            Some(servo) if servo.supports(features::DOWNLOADS) => Ok(servo.start_download(uri)),
+            Some(_) => Err(glib::Error::new(
+                gio::IOErrorEnum::NotSupported,
+                "The web process can't download",
+            )),
+            None => Err(glib::Error::new(
+                gio::IOErrorEnum::NotConnected,
+                "The web process is not running",
+            )),
+        };
+        let download_id = match download_id {
+            Ok(download_id) => download_id,
+            Err(err) => {
+                let download = Download::new(0, self, uri);
+                // Let the caller connect to the signals first
+                let failed_download = download.clone();
+                glib::idle_add_local_once(move || failed_download.fail(err));
+                return download;
+            }
+        };
+
+        let download = Download::new(download_id, self, uri);
+        imp.downloads
+            .borrow_mut()
+            .insert(download_id, download.clone());
+        self.emit_by_name::<()>("download-started", &[&download]);
+        download
+    }
+
+    pub(crate) fn set_download_destination(&self, download_id: u64, path: &str) {
+        if let Some(servo) = self.imp().servo_runner.borrow().as_ref() {
+            servo.download_destination(download_id, path);
+        }
+    }
+
+    pub(crate) fn cancel_download(&self, download_id: u64) {
+        let imp = self.imp();
+        if imp.downloads.borrow_mut().remove(&download_id).is_some()
+            && let Some(servo) = imp.servo_runner.borrow().as_ref()
+        {
+            servo.cancel_download(download_id);
+        }
+    }
+
+    fn download(&self, download_id: u64) -> Option<Download> {
+        self.imp().downloads.borrow().get(&download_id).cloned()
+    }
+
     pub fn reload(&self) {
         let imp = self.imp();
         if let Some(servo) = imp.servo_runner.borrow().as_ref() {
@@ -223,6 +802,300 @@ impl WebView {
         }
     }
 
+    /// Evaluates `script` in the current page and returns its result.
+    pub async fn evaluate_javascript(
+        &self,
+        script: &str,
+        cancellable: Option<&gio::Cancellable>,
+    ) -> Result<JsValue, glib::Error> {
+        let receiver = self
+            .imp()
+            .servo_runner
+            .borrow()
+            .as_ref()
+            .map(|servo| servo.evaluate_javascript(script))
+            .ok_or_else(|| {
+                glib::Error::new(
+                    gio::IOErrorEnum::NotConnected,
+                    "The web process is not running",
+                )
+            })?;
+
+        let result = match cancellable {
+            Some(cancellable) => {
+                gio::CancellableFuture::new(receiver.recv(), cancellable.clone()).await?
+            }
+            None => receiver.recv().await,
+        };
+        result.map_err(|_| {
+            glib::Error::new(gio::IOErrorEnum::BrokenPipe, "The web process terminated")
+        })?
+    }
+
+    /// Captures `region` of the page as PNG data.
+    ///
+    /// Full-document snapshots briefly render the page at the size of the
+    /// whole document, capped at 16384 pixels on each side. Fails with
+    /// `InvalidArgument` unless the scale is positive, and with
+    /// `NotSupported` if the web process can't take snapshots.
+    pub async fn snapshot_png(
+        &self,
+        region: SnapshotRegion,
+        options: SnapshotOptions,
+        cancellable: Option<&gio::Cancellable>,
+    ) -> Result<glib::Bytes, glib::Error> {
+        let receiver = self
+            .imp()
+            .servo_runner
+            .borrow()
+            .as_ref()
+            .map(|servo| servo.snapshot(region, options))
+            .ok_or_else(|| {
+                glib::Error::new(
+                    gio::IOErrorEnum::NotConnected,
+                    "The web process is not running",
+                )
+            })?;
+
+        let result = match cancellable {
+            Some(cancellable) => {
+                gio::CancellableFuture::new(receiver.recv(), cancellable.clone()).await?
+            }
+            None => receiver.recv().await,
+        };
+        result.map_err(|_| {
+            glib::Error::new(gio::IOErrorEnum::BrokenPipe, "The web process terminated")
+        })?
+    }
+
+    /// Captures `region` of the page as a texture, see
+    /// [`WebView::snapshot_png`].
+    pub async fn snapshot(
+        &self,
+        region: SnapshotRegion,
+        options: SnapshotOptions,
+        cancellable: Option<&gio::Cancellable>,
+    ) -> Result<gdk::Texture, glib::Error> {
+        let receiver = self
+            .imp()
+            .servo_runner
+            .borrow()
+            .as_ref()
+            .map(|servo| servo.snapshot_texture(region, options))
+            .ok_or_else(|| {
+                glib::Error::new(
+                    gio::IOErrorEnum::NotConnected,
+                    "The web process is not running",
+                )
+            })?;
+
+        let result = match cancellable {
+            Some(cancellable) => {
+                gio::CancellableFuture::new(receiver.recv(), cancellable.clone()).await?
+            }
+            None => receiver.recv().await,
+        };
+        result.map_err(|_| {
+            glib::Error::new(gio::IOErrorEnum::BrokenPipe, "The web process terminated")
+        })?
+    }
+
+    fn start_servo_runner(&self) {
+        let imp = self.imp();
+
+        let web_context = self
+            .web_context()
+            .unwrap_or_else(WebContext::default_context);
+        let servo_runner = match web_context.create_view(self) {
+            Ok(servo_runner) => servo_runner,
+            Err(err) => {
+                self.process_runner_failed(err);
+                return;
+            }
+        };
+
+        // A restarted view may join a process other views already started
+        for scheme in imp.uri_schemes.borrow().keys() {
+            if let Err(err) = servo_runner.register_uri_scheme(scheme) {
+                warn!("{}", err.message());
+            }
+        }
+
+        imp.servo_runner.replace(Some(servo_runner));
+    }
+
+    /// Shows that the web view can't work, either because the runner
+    /// couldn't be spawned or because it doesn't understand the widget.
+    pub(crate) fn process_runner_failed(&self, err: glib::Error) {
+        warn!("Failed to start servo runner: {err}");
+
+        let imp = self.imp();
+        imp.servo_runner.replace(None);
+        imp.placeholder
+            .replace(Some("Could not start the web engine".to_string()));
+        imp.memory_texture.replace(None);
+        self.queue_draw();
+        self.set_loading(false, 0.0);
+
+        // Emit from an idle so that handlers connected right after
+        // construction get to see the error
+        let obj_weak = self.downgrade();
+        glib::idle_add_local_once(move || {
+            if let Some(obj) = obj_weak.upgrade() {
+                obj.emit_by_name::<()>("web-process-failed", &[&err]);
+            }
+        });
+    }
+
+    pub(crate) fn process_runner_terminated(&self, reason: TerminationReason, code: i32) {
+        warn!("Servo runner terminated: {reason:?} ({code})");
+
+        let imp = self.imp();
+        imp.servo_runner.replace(None);
+        self.set_ime_state(ImeState::default());
+        imp.placeholder
+            .replace(Some("This page crashed".to_string()));
+        imp.memory_texture.replace(None);
+        self.queue_draw();
+        self.set_loading(false, 0.0);
+
+        let downloads: Vec<Download> = imp
+            .downloads
+            .borrow_mut()
+            .drain()
+            .map(|(_, download)| download)
+            .collect();
+        for download in downloads {
+            download.fail(glib::Error::new(
+                gio::IOErrorEnum::BrokenPipe,
+                "The web process terminated",
+            ));
+        }
+
+        self.emit_by_name::<()>("web-process-terminated", &[&reason, &code]);
+
+        if !self.restart_on_crash() {
+            return;
+        }
+
+        info!("Restarting servo runner");
+        self.start_servo_runner();
+        if let Some(servo) = imp.servo_runner.borrow().as_ref() {
+            if self.width() > 0 && self.height() > 0 {
+                servo.resize(self.width() as u32, self.height() as u32);
+            }
+            if let Some(uri) = imp.uri.borrow().as_ref() {
+                servo.load_url(uri);
+            }
+        }
+    }
+
+    fn default_context_menu(&self, hit_test_result: &HitTestResult) -> gio::Menu {
+        let menu = gio::Menu::new();
+
+        let navigation_section = gio::Menu::new();
+        navigation_section.append(Some("Back"), Some("web-view.go-back"));
+        navigation_section.append(Some("Forward"), Some("web-view.go-forward"));
+        navigation_section.append(Some("Reload"), Some("web-view.reload"));
+        menu.append_section(None, &navigation_section);
+
+        let content_section = gio::Menu::new();
+        let items = [
+            ("Copy", "web-view.copy", hit_test_result.selected_text()),
+            (
+                "Copy Link",
+                "web-view.copy-link",
+                hit_test_result.link_uri(),
+            ),
+            (
+                "Open Image",
+                "web-view.open-image",
+                hit_test_result.image_uri(),
+            ),
+        ];
+        for (label, action, target) in items {
+            if let Some(target) = target {
+                let item = gio::MenuItem::new(Some(label), None);
+                item.set_action_and_target_value(Some(action), Some(&target.to_variant()));
+                content_section.append_item(&item);
+            }
+        }
+        menu.append_section(None, &content_section);
+
+        menu
+    }
+
+    fn process_create_request(&self, request_id: u64) {
+        let web_view = self.emit_by_name::<Option<WebView>>("create", &[]);
+        let adopted = web_view.is_some_and(|web_view| web_view.adopt_opened_view(self, request_id));
+        if !adopted && let Some(servo) = self.imp().servo_runner.borrow().as_ref() {
+            servo.create_response(request_id, false);
+        }
+    }
+
+    /// Makes this view show the window `opener`'s page opened through
+    /// `request_id`.
+    fn adopt_opened_view(&self, opener: &WebView, request_id: u64) -> bool {
+        let imp = self.imp();
+        {
+            let servo_runner = imp.servo_runner.borrow();
+            let opener_runner = opener.imp().servo_runner.borrow();
+            let (Some(servo), Some(opener_servo)) = (servo_runner.as_ref(), opener_runner.as_ref())
+            else {
+                return false;
+            };
+            if self == opener || !servo.shares_process(opener_servo) {
+                warn!(
+                    "Web views returned from create have to be new views sharing the opener's context"
+                );
+                return false;
+            }
+
+            servo.create_response(request_id, true);
+            if self.width() > 0 && self.height() > 0 {
+                servo.resize(self.width() as u32, self.height() as u32);
+            }
+        }
+
+        imp.opener.set(Some(opener));
+        self.notify_opener();
+        true
+    }
+
+    fn show_context_menu(&self, context_menu: crate::proto_ipc::ContextMenu) {
+        let hit_test_result = HitTestResult::new(
+            context_menu.link_url,
+            context_menu.image_url,
+            context_menu.selected_text,
+            context_menu.editable,
+        );
+        let menu = self.default_context_menu(&hit_test_result);
+        if self.emit_by_name::<bool>("context-menu", &[&menu, &hit_test_result]) {
+            return;
+        }
+
+        let imp = self.imp();
+        let popover = imp
+            .context_menu
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                let popover = gtk::PopoverMenu::from_model(None::<&gio::MenuModel>);
+                popover.set_parent(self);
+                popover.set_has_arrow(false);
+                popover.set_halign(gtk::Align::Start);
+                popover
+            })
+            .clone();
+        popover.set_menu_model(Some(&menu));
+        popover.set_pointing_to(Some(&gdk::Rectangle::new(
+            context_menu.x as i32,
+            context_menu.y as i32,
+            1,
+            1,
+        )));
+        popover.popup();
+    }
+
     fn translate_event_coordinates(&self, event: &gdk::Event) -> Option<(f64, f64)> {
         let root = self.root()?;
         let native = root.native()?;
@@ -238,33 +1111,331 @@ impl WebView {
         Some((translated.x() as f64, translated.y() as f64))
     }
 
-    fn process_servo_event(&self, event: ServoEvent) {
+    fn update_texture(
+        &self,
+        frame_buffer_id: u32,
+        buffer_index: u32,
+        width: u32,
+        height: u32,
+        stride: u32,
+        damage: Option<&gdk::cairo::Region>,
+    ) {
+        let imp = self.imp();
+
+        let Some(bytes) = imp
+            .servo_runner
+            .borrow()
+            .as_ref()
+            .and_then(|servo| servo.frame_bytes(frame_buffer_id, buffer_index, stride, height))
+        else {
+            return;
+        };
+
+        let mut builder = gdk::MemoryTextureBuilder::new()
+            .set_bytes(Some(&bytes))
+            .set_width(width as i32)
+            .set_height(height as i32)
+            .set_stride(stride as usize)
+            .set_format(gdk::MemoryFormat::R8g8b8a8);
+
+        // Let GTK only upload the damaged area of the previous texture
+        if let Some(region) = damage
+            && let Some(previous) = imp.memory_texture.borrow().as_ref()
+        {
+            builder = builder
+                .set_update_texture(Some(previous))
+                .set_update_region(Some(region));
+        }
+
+        imp.memory_texture.replace(Some(builder.build()));
+        imp.placeholder.replace(None);
+        self.queue_draw();
+    }
+
+    /// Lets the input method handle the current key event while a text
+    /// field has the focus, and tells what it did with it.
+    ///
+    /// The text a key press commits outside of a composition is held back
+    /// to go with the key, so that typing through the input method reads
+    /// as regular key presses.
+    fn filter_input_method_event(&self, controller: &gtk::EventControllerKey) -> InputMethodFilter {
+        let imp = self.imp();
+        let Some(event) = controller.current_event().filter(|_| imp.ime_active.get()) else {
+            return InputMethodFilter::Ignored;
+        };
+        if event.event_type() != gdk::EventType::KeyPress {
+            imp.im_context.filter_keypress(&event);
+            return InputMethodFilter::Ignored;
+        }
+
+        imp.ime_key_commit.replace(Some(String::new()));
+        let filtered = imp.im_context.filter_keypress(&event);
+        let key_commit = imp.ime_key_commit.take().unwrap_or_default();
+        if !key_commit.is_empty() {
+            InputMethodFilter::Committed(key_commit)
+        } else if filtered {
+            InputMethodFilter::Composing
+        } else {
+            InputMethodFilter::Ignored
+        }
+    }
+
+    /// Points the input method at the focused text field of the page, or
+    /// stops it when there is none.
+    fn set_ime_state(&self, ime_state: ImeState) {
+        let imp = self.imp();
+        let im_context = &imp.im_context;
+        imp.ime_active.set(ime_state.active);
+        if !ime_state.active {
+            // The runner ends the composition along with the field
+            imp.ime_composing.set(false);
+            im_context.reset();
+            im_context.focus_out();
+            return;
+        }
+
+        let purpose = match crate::proto_ipc::InputPurpose::try_from(ime_state.purpose)
+            .unwrap_or(crate::proto_ipc::InputPurpose::FreeForm)
+        {
+            crate::proto_ipc::InputPurpose::FreeForm => gtk::InputPurpose::FreeForm,
+            crate::proto_ipc::InputPurpose::Email => gtk::InputPurpose::Email,
+            crate::proto_ipc::InputPurpose::Number => gtk::InputPurpose::Number,
+            crate::proto_ipc::InputPurpose::Password => gtk::InputPurpose::Password,
+            crate::proto_ipc::InputPurpose::Phone => gtk::InputPurpose::Phone,
+            crate::proto_ipc::InputPurpose::Url => gtk::InputPurpose::Url,
+        };
+        im_context.set_input_purpose(purpose);
+        imp.ime_surrounding
+            .replace((ime_state.surrounding_text, ime_state.cursor_index));
+        im_context.set_cursor_location(&gdk::Rectangle::new(
+            ime_state.x,
+            ime_state.y,
+            ime_state.width,
+            ime_state.height,
+        ));
+        if self.has_focus() {
+            im_context.focus_in();
+        }
+    }
+
+    /// Describes a key press or release to the page, keeping track of the
+    /// keys held down and of dead keys.
+    fn key_event(
+        &self,
+        keyval: gdk::Key,
+        keycode: u32,
+        state: gdk::ModifierType,
+        pressed: bool,
+    ) -> Option<KeyEvent> {
+        let imp = self.imp();
+        let (key, is_character, location) = imp.key_tables.key_from_keyval(keyval.into_glib())?;
+
+        // GTK sends presses without releases while a key is held down
+        let repeat = if pressed {
+            !imp.pressed_keys.borrow_mut().insert(keycode)
+        } else {
+            imp.pressed_keys.borrow_mut().remove(&keycode);
+            false
+        };
+
+        // A dead key composes with the next character, which is over once
+        // that character is released
+        let is_composing = imp.composing.get();
+        if pressed && key_tables::is_dead_key(keyval.into_glib()) {
+            imp.composing.set(true);
+        } else if !pressed && is_character {
+            imp.composing.set(false);
+        }
+
+        // Modifier keys count as held down in their own press, and not in
+        // their release
+        let key_modifier = modifiers::key_modifier(keyval);
+        if key_modifier == Modifiers::ALT_GRAPH {
+            imp.alt_graph.set(pressed);
+        }
+        let mut modifiers = self.dom_modifiers(state);
+        modifiers.set(key_modifier, pressed);
+
+        Some(KeyEvent {
+            key,
+            is_character,
+            location,
+            key_code: keycode,
+            code: imp.key_tables.code_from_keycode(keycode),
+            modifiers,
+            repeat,
+            is_composing,
+        })
+    }
+
+    /// Sends a scroll by (`dx`, `dy`) in the unit of `controller`, at the
+    /// position of its current event, or of the last one that had one.
+    fn send_scroll(
+        &self,
+        controller: &gtk::EventControllerScroll,
+        dx: f64,
+        dy: f64,
+        phase: ScrollPhase,
+    ) {
+        let imp = self.imp();
+        if let Some(event) = controller.current_event()
+            && let Some(position) = self.translate_event_coordinates(&event)
+        {
+            imp.scroll_position.set(position);
+        }
+        if let Some(servo) = imp.servo_runner.borrow().as_ref() {
+            let (x, y) = imp.scroll_position.get();
+            servo.scroll(&ScrollEvent {
+                x,
+                y,
+                dx,
+                dy,
+                unit: controller.unit(),
+                phase,
+                modifiers: self.dom_modifiers(controller.current_event_state()),
+            });
+        }
+    }
+
+    /// Keeps scrolling at (`velocity_x`, `velocity_y`) pixels per second
+    /// after a touchpad gesture, slowing down on every frame.
+    fn start_kinetic_scroll(&self, velocity_x: f64, velocity_y: f64, modifiers: Modifiers) {
+        self.stop_kinetic_scroll();
+
+        let mut velocity = (velocity_x, velocity_y);
+        let mut last_frame_time = None;
+        let tick_callback = self.add_tick_callback(move |obj, frame_clock| {
+            let frame_time = frame_clock.frame_time();
+            let elapsed = last_frame_time
+                .map(|last_frame_time| (frame_time - last_frame_time) as f64 / 1_000_000.0)
+                .unwrap_or(0.0);
+            last_frame_time = Some(frame_time);
+
+            // Moves by the integral of the exponentially decaying velocity
+            let decay = (-elapsed / KINETIC_TIME_CONSTANT).exp();
+            let dx = velocity.0 * KINETIC_TIME_CONSTANT * (1.0 - decay);
+            let dy = velocity.1 * KINETIC_TIME_CONSTANT * (1.0 - decay);
+            velocity = (velocity.0 * decay, velocity.1 * decay);
+
+            let imp = obj.imp();
+            if let Some(servo) = imp.servo_runner.borrow().as_ref() {
+                let (x, y) = imp.scroll_position.get();
+                servo.scroll(&ScrollEvent {
+                    x,
+                    y,
+                    dx,
+                    dy,
+                    unit: gdk::ScrollUnit::Surface,
+                    phase: ScrollPhase::Momentum,
+                    modifiers,
+                });
+            }
+
+            if velocity.0.hypot(velocity.1) < KINETIC_MIN_VELOCITY {
+                // Breaking removes the callback, which can't be removed again
+                imp.kinetic_scroll.take();
+                return glib::ControlFlow::Break;
+            }
+            glib::ControlFlow::Continue
+        });
+        self.imp().kinetic_scroll.replace(Some(tick_callback));
+    }
+
+    fn stop_kinetic_scroll(&self) {
+        if let Some(tick_callback) = self.imp().kinetic_scroll.take() {
+            tick_callback.remove();
+        }
+    }
+
+    /// The id of the touch point of `event`, which stays the same from the
+    /// begin of its sequence to its end. Ids of `ended` sequences are free
+    /// to be reused.
+    fn touch_id(&self, event: &gdk::Event, ended: bool) -> u32 {
+        // Sequences are only told apart by their address
+        let sequence = event
+            .event_sequence()
+            .map(|sequence| sequence.to_glib_none().0 as usize)
+            .unwrap_or_default();
+
+        let mut touch_ids = self.imp().touch_ids.borrow_mut();
+        let id = match touch_ids.get(&sequence) {
+            Some(id) => *id,
+            None => {
+                // Pages expect small ids, so take the lowest one unused
+                let id = (0..)
+                    .find(|id| !touch_ids.values().any(|used| used == id))
+                    .expect("Ran out of touch ids");
+                touch_ids.insert(sequence, id);
+                id
+            }
+        };
+        if ended {
+            touch_ids.remove(&sequence);
+        }
+        id
+    }
+
+    /// The DOM modifiers for `state`, completed with the lock states of the
+    /// keyboard.
+    fn dom_modifiers(&self, state: gdk::ModifierType) -> Modifiers {
+        let alt_graph = self.imp().alt_graph.get();
+        let locks = self
+            .display()
+            .default_seat()
+            .and_then(|seat| seat.keyboard())
+            .map(|keyboard| LockState::from_keyboard(&keyboard, alt_graph))
+            .unwrap_or(LockState {
+                alt_graph,
+                ..Default::default()
+            });
+        modifiers::dom_modifiers(state, locks)
+    }
+
+    fn set_loading(&self, is_loading: bool, progress: f64) {
+        let imp = self.imp();
+        if imp.is_loading.replace(is_loading) != is_loading {
+            self.notify_is_loading();
+        }
+        if imp.estimated_load_progress.replace(progress) != progress {
+            self.notify_estimated_load_progress();
+        }
+    }
+
+    pub(crate) fn process_servo_event(&self, event: ServoEvent) {
         let Some(event_type) = event.event else {
             return;
         };
 
         match event_type {
             servo_event::Event::FrameReady(frame_ready) => {
-                let rgba_image = RgbaImage::from_raw(
+                self.update_texture(
+                    frame_ready.frame_buffer_id,
+                    frame_ready.buffer_index,
                     frame_ready.width,
                     frame_ready.height,
-                    frame_ready.rgba_data,
-                )
-                .unwrap();
-
-                let imp = self.imp();
-
-                let bytes = glib::Bytes::from(&rgba_image.as_raw()[..]);
-                let texture = gdk::MemoryTexture::new(
-                    rgba_image.width() as i32,
-                    rgba_image.height() as i32,
-                    gdk::MemoryFormat::R8g8b8a8,
-                    &bytes,
-                    (rgba_image.width() * 4) as usize,
+                    frame_ready.stride,
+                    None,
                 );
+            }
+            servo_event::Event::FrameDamage(frame_damage) => {
+                let region = gdk::cairo::Region::create();
+                for rect in &frame_damage.rects {
+                    let _ = region.union_rectangle(&gdk::cairo::RectangleInt::new(
+                        rect.x as i32,
+                        rect.y as i32,
+                        rect.width as i32,
+                        rect.height as i32,
+                    ));
+                }
 
-                imp.memory_texture.replace(Some(texture));
-                self.queue_draw();
+                self.update_texture(
+                    frame_damage.frame_buffer_id,
+                    frame_damage.buffer_index,
+                    frame_damage.width,
+                    frame_damage.height,
+                    frame_damage.stride,
+                    Some(&region),
+                );
             }
             servo_event::Event::CursorChanged(cursor_changed) => {
                 let gdk_cursor = gdk::Cursor::from_name(&cursor_changed.cursor, None);
@@ -272,14 +1443,184 @@ impl WebView {
                     self.set_cursor(Some(&cursor));
                 }
             }
-            servo_event::Event::LogMessage(log_msg) => {
+            servo_event::Event::TitleChanged(title_changed) => {
+                let title = Some(title_changed.title).filter(|title| !title.is_empty());
+                if *self.imp().title.borrow() != title {
+                    self.imp().title.replace(title);
+                    self.notify_title();
+                }
+            }
+            servo_event::Event::UrlChanged(url_changed) => {
+                let uri = Some(url_changed.url);
+                if *self.imp().uri.borrow() != uri {
+                    self.imp().uri.replace(uri);
+                    self.notify_uri();
+                }
+            }
+            servo_event::Event::LoadStart(_) => {
+                self.set_loading(true, 0.1);
+            }
+            servo_event::Event::LoadProgress(load_progress) => {
+                self.set_loading(true, load_progress.progress);
+            }
+            servo_event::Event::LoadEnd(_) => {
+                self.set_loading(false, 1.0);
+            }
+            servo_event::Event::LoadFailed(load_failed) => {
+                warn!("Failed to load: {}", load_failed.error);
+            }
+            servo_event::Event::HistoryChanged(history_changed) => {
+                let imp = self.imp();
+                if imp.can_go_back.replace(history_changed.can_go_back)
+                    != history_changed.can_go_back
+                {
+                    self.notify_can_go_back();
+                }
+                if imp.can_go_forward.replace(history_changed.can_go_forward)
+                    != history_changed.can_go_forward
+                {
+                    self.notify_can_go_forward();
+                }
+                self.action_set_enabled("web-view.go-back", history_changed.can_go_back);
+                self.action_set_enabled("web-view.go-forward", history_changed.can_go_forward);
+            }
+            servo_event::Event::JavascriptResult(javascript_result) => {
+                if let Some(servo_runner) = self.imp().servo_runner.borrow().as_ref() {
+                    servo_runner.javascript_result(javascript_result);
+                }
+            }
+            servo_event::Event::SnapshotResult(snapshot_result) => {
                 if let Some(servo_runner) = self.imp().servo_runner.borrow().as_ref() {
-                    servo_runner
-                        .handle_log_message(LogLevel::from(log_msg.level), &log_msg.message);
+                    servo_runner.snapshot_result(snapshot_result);
+                }
+            }
+            servo_event::Event::SchemeRequest(scheme_request) => {
+                let request = UriSchemeRequest::new(
+                    scheme_request.request_id,
+                    scheme_request.uri,
+                    scheme_request.method,
+                    self,
+                );
+                let handler = self
+                    .imp()
+                    .uri_schemes
+                    .borrow()
+                    .get(request.scheme())
+                    .cloned();
+                match handler {
+                    Some(handler) => handler(request),
+                    None => request.finish_error("Unknown URI scheme"),
                 }
             }
-            _ => {
-                info!("Unhandled event type: {:?}", event_type);
+            servo_event::Event::NavigationRequest(navigation_request) => {
+                let navigation_type = match crate::proto_ipc::NavigationType::try_from(
+                    navigation_request.navigation_type,
+                ) {
+                    Ok(crate::proto_ipc::NavigationType::LinkClicked) => {
+                        NavigationType::LinkClicked
+                    }
+                    _ => NavigationType::Other,
+                };
+                let decision = NavigationPolicyDecision::new(
+                    navigation_request.request_id,
+                    self,
+                    navigation_request.url,
+                    navigation_type,
+                    navigation_request.button,
+                    modifiers::gdk_modifiers(Modifiers::from_bits_truncate(
+                        navigation_request.modifiers,
+                    )),
+                );
+                if !self.emit_by_name::<bool>("decide-policy", &[&decision]) {
+                    decision.allow();
+                }
+            }
+            servo_event::Event::ShowDialog(show_dialog) => {
+                let dialog_type =
+                    match crate::proto_ipc::DialogType::try_from(show_dialog.dialog_type) {
+                        Ok(crate::proto_ipc::DialogType::Confirm) => ScriptDialogType::Confirm,
+                        Ok(crate::proto_ipc::DialogType::Prompt) => ScriptDialogType::Prompt,
+                        Ok(crate::proto_ipc::DialogType::BeforeUnload) => {
+                            ScriptDialogType::BeforeUnload
+                        }
+                        _ => ScriptDialogType::Alert,
+                    };
+                let dialog = ScriptDialog::new(
+                    show_dialog.request_id,
+                    self,
+                    dialog_type,
+                    show_dialog.message,
+                    show_dialog.default_text,
+                );
+                if !self.emit_by_name::<bool>("script-dialog", &[&dialog]) {
+                    dialog.run_default(self.root().and_downcast::<gtk::Window>());
+                }
+            }
+            servo_event::Event::ImeState(ime_state) => self.set_ime_state(ime_state),
+            servo_event::Event::ContextMenu(context_menu) => {
+                self.show_context_menu(context_menu);
+            }
+            servo_event::Event::FileChooserRequest(file_chooser_request) => {
+                let request = FileChooserRequest::new(
+                    file_chooser_request.request_id,
+                    self,
+                    file_chooser_request.accept,
+                    file_chooser_request.multiple,
+                    file_chooser_request.directory,
+                );
+                if !self.emit_by_name::<bool>("run-file-chooser", &[&request]) {
+                    request.run_default(self.root().and_downcast::<gtk::Window>());
+                }
+            }
+            servo_event::Event::DownloadResponse(download_response) => {
+                if let Some(download) = self.download(download_response.download_id) {
+                    download
+                        .set_response(download_response.mime_type, download_response.total_bytes);
+                    let suggested_filename = download_response.suggested_filename;
+                    if !self.emit_by_name::<bool>(
+                        "decide-destination",
+                        &[&download, &suggested_filename],
+                    ) {
+                        download.set_default_destination(&suggested_filename);
+                    }
+                }
+            }
+            servo_event::Event::DownloadProgress(download_progress) => {
+                if let Some(download) = self.download(download_progress.download_id) {
+                    download.set_received_bytes(download_progress.received_bytes);
+                }
+            }
+            servo_event::Event::DownloadFinished(download_finished) => {
+                let download = self
+                    .imp()
+                    .downloads
+                    .borrow_mut()
+                    .remove(&download_finished.download_id);
+                if let Some(download) = download {
+                    download.finish();
+                }
+            }
+            servo_event::Event::DownloadFailed(download_failed) => {
+                let download = self
+                    .imp()
+                    .downloads
+                    .borrow_mut()
+                    .remove(&download_failed.download_id);
+                if let Some(download) = download {
+                    download.fail(glib::Error::new(
+                        gio::IOErrorEnum::Failed,
+                        &download_failed.error,
+                    ));
+                }
+            }
+            servo_event::Event::CreateRequest(create_request) => {
+                self.process_create_request(create_request.request_id);
+            }
+            servo_event::Event::Closed(_) => {
+                self.emit_by_name::<()>("close", &[]);
+            }
+            servo_event::Event::LogMessage(log_msg) => {
+                RunnerProcess::handle_log_message(LogLevel::from(log_msg.level), &log_msg.message);
             }
         }
     }
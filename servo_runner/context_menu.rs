@@ -0,0 +1,62 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use embedder_traits::{ContextMenuElementInformationFlags, JSValue};
+use servo::{ContextMenu, WebView};
+use servo_gtk::proto_ipc::{self, servo_event};
+
+use crate::send_event;
+
+/// Reports the context menu Servo wants to show so the widget shows its
+/// own instead.
+///
+/// Servo only asks once the page's `contextmenu` event went through
+/// uncancelled, and hit-tests the element itself, so the page can't make
+/// links and images point elsewhere. It doesn't tell the selected text,
+/// which is read from the page when there is a selection.
+pub(crate) fn report_context_menu(webview_id: u64, webview: &WebView, context_menu: ContextMenu) {
+    let position = context_menu.position().min;
+    let element_info = context_menu.element_info();
+    let flags = element_info.flags;
+    let event = proto_ipc::ContextMenu {
+        x: position.x as f64,
+        y: position.y as f64,
+        link_url: element_info
+            .link_url
+            .as_ref()
+            .filter(|_| flags.contains(ContextMenuElementInformationFlags::Link))
+            .map(|url| url.to_string())
+            .unwrap_or_default(),
+        image_url: element_info
+            .image_url
+            .as_ref()
+            .filter(|_| flags.contains(ContextMenuElementInformationFlags::Image))
+            .map(|url| url.to_string())
+            .unwrap_or_default(),
+        selected_text: String::new(),
+        editable: flags.contains(ContextMenuElementInformationFlags::EditableText),
+    };
+    // The widget's menu handles the actions, Servo's isn't shown
+    context_menu.dismiss();
+
+    if !flags.contains(ContextMenuElementInformationFlags::Selection) {
+        let _ = send_event(webview_id, servo_event::Event::ContextMenu(event));
+        return;
+    }
+    webview.evaluate_javascript("String(window.getSelection() || \"\")", move |result| {
+        let selected_text = match result {
+            Ok(JSValue::String(selected_text)) => selected_text,
+            Ok(_) => String::new(),
+            Err(err) => {
+                log::warn!("Failed to read the selection: {err:?}");
+                String::new()
+            }
+        };
+        let event = proto_ipc::ContextMenu {
+            selected_text,
+            ..event
+        };
+        let _ = send_event(webview_id, servo_event::Event::ContextMenu(event));
+    });
+}
@@ -0,0 +1,142 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use servo_gtk::proto_ipc::DamageRect;
+
+const TILE_SIZE: u32 = 64;
+const BYTES_PER_PIXEL: u32 = 4;
+
+fn tile_changed(
+    previous: &[u8],
+    current: &[u8],
+    stride: u32,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> bool {
+    let start = (x * BYTES_PER_PIXEL) as usize;
+    let end = ((x + width) * BYTES_PER_PIXEL) as usize;
+    (y..y + height).any(|row| {
+        let row_offset = (row * stride) as usize;
+        previous[row_offset + start..row_offset + end]
+            != current[row_offset + start..row_offset + end]
+    })
+}
+
+/// Compares two frames of the same size and returns the rectangles that
+/// changed, as rows of adjacent `TILE_SIZE` tiles.
+pub(crate) fn damaged_rects(
+    previous: &[u8],
+    current: &[u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+) -> Vec<DamageRect> {
+    let mut rects = Vec::new();
+
+    for y in (0..height).step_by(TILE_SIZE as usize) {
+        let tile_height = TILE_SIZE.min(height - y);
+        let mut pending: Option<DamageRect> = None;
+
+        for x in (0..width).step_by(TILE_SIZE as usize) {
+            let tile_width = TILE_SIZE.min(width - x);
+            if tile_changed(previous, current, stride, x, y, tile_width, tile_height) {
+                match pending.as_mut() {
+                    Some(rect) => rect.width += tile_width,
+                    None => {
+                        pending = Some(DamageRect {
+                            x,
+                            y,
+                            width: tile_width,
+                            height: tile_height,
+                        })
+                    }
+                }
+            } else if let Some(rect) = pending.take() {
+                rects.push(rect);
+            }
+        }
+
+        rects.extend(pending);
+    }
+
+    rects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(stride: u32, height: u32) -> Vec<u8> {
+        vec![0; (stride * height) as usize]
+    }
+
+    fn touch(frame: &mut [u8], stride: u32, x: u32, y: u32) {
+        frame[(y * stride + x * BYTES_PER_PIXEL) as usize] ^= 0xff;
+    }
+
+    fn rect(x: u32, y: u32, width: u32, height: u32) -> DamageRect {
+        DamageRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn identical_frames_have_no_damage() {
+        let previous = frame(400, 100);
+        assert!(damaged_rects(&previous, &previous.clone(), 100, 100, 400).is_empty());
+    }
+
+    #[test]
+    fn partial_last_tiles_are_clipped() {
+        let (width, height, stride) = (100, 70, 400);
+        let previous = frame(stride, height);
+        let mut current = previous.clone();
+        touch(&mut current, stride, 99, 69);
+
+        assert_eq!(
+            damaged_rects(&previous, &current, width, height, stride),
+            [rect(64, 64, 36, 6)]
+        );
+    }
+
+    #[test]
+    fn adjacent_tiles_are_merged_by_row() {
+        let (width, height, stride) = (256, 128, 1024);
+        let previous = frame(stride, height);
+        let mut current = previous.clone();
+        touch(&mut current, stride, 0, 0);
+        touch(&mut current, stride, 70, 10);
+        touch(&mut current, stride, 200, 10);
+        touch(&mut current, stride, 10, 100);
+
+        assert_eq!(
+            damaged_rects(&previous, &current, width, height, stride),
+            [
+                rect(0, 0, 128, 64),
+                rect(192, 0, 64, 64),
+                rect(0, 64, 64, 64)
+            ]
+        );
+    }
+
+    #[test]
+    fn row_padding_is_ignored() {
+        let (width, height, stride) = (10, 10, 48);
+        let previous = frame(stride, height);
+        let mut current = previous.clone();
+        current[(3 * stride + width * BYTES_PER_PIXEL) as usize] = 1;
+        assert!(damaged_rects(&previous, &current, width, height, stride).is_empty());
+
+        touch(&mut current, stride, 9, 3);
+        assert_eq!(
+            damaged_rects(&previous, &current, width, height, stride),
+            [rect(0, 0, 10, 10)]
+        );
+    }
+}
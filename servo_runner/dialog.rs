@@ -0,0 +1,139 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use embedder_traits::{AlertResponse, ConfirmResponse, PromptResponse, SimpleDialog};
+use servo::AllowOrDenyRequest;
+use servo_gtk::proto_ipc::{DialogType, ShowDialog, servo_event};
+
+use crate::send_event;
+
+enum PendingDialog {
+    Simple(SimpleDialog),
+    BeforeUnload(AllowOrDenyRequest),
+}
+
+impl PendingDialog {
+    fn respond(self, confirmed: bool, text: String) {
+        match self {
+            PendingDialog::Simple(SimpleDialog::Alert {
+                response_sender, ..
+            }) => {
+                let _ = response_sender.send(AlertResponse::Ok);
+            }
+            PendingDialog::Simple(SimpleDialog::Confirm {
+                response_sender, ..
+            }) => {
+                let _ = response_sender.send(if confirmed {
+                    ConfirmResponse::Ok
+                } else {
+                    ConfirmResponse::Cancel
+                });
+            }
+            PendingDialog::Simple(SimpleDialog::Prompt {
+                response_sender, ..
+            }) => {
+                let _ = response_sender.send(if confirmed {
+                    PromptResponse::Ok(text)
+                } else {
+                    PromptResponse::Cancel
+                });
+            }
+            PendingDialog::BeforeUnload(unload_request) => {
+                if confirmed {
+                    unload_request.allow();
+                } else {
+                    unload_request.deny();
+                }
+            }
+        }
+    }
+}
+
+/// Script dialogs waiting for the user to answer them in the widget.
+///
+/// Unlike navigations these don't time out, the page is blocked until the
+/// user replies.
+pub(crate) struct PendingDialogs {
+    webview_id: u64,
+    next_request_id: Cell<u64>,
+    dialogs: RefCell<HashMap<u64, PendingDialog>>,
+}
+
+impl PendingDialogs {
+    pub(crate) fn new(webview_id: u64) -> Self {
+        Self {
+            webview_id,
+            next_request_id: Cell::new(0),
+            dialogs: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn add(
+        &self,
+        dialog_type: DialogType,
+        message: String,
+        default_text: String,
+        dialog: PendingDialog,
+    ) {
+        let request_id = self.next_request_id.get();
+        self.next_request_id.set(request_id + 1);
+
+        let event = servo_event::Event::ShowDialog(ShowDialog {
+            request_id,
+            dialog_type: dialog_type as i32,
+            message,
+            default_text,
+        });
+        if send_event(self.webview_id, event).is_err() {
+            // Nobody can answer, leave the page as if the user didn't confirm
+            // anything, but don't trap it on unload
+            let confirmed = matches!(dialog, PendingDialog::BeforeUnload(_));
+            dialog.respond(confirmed, String::new());
+            return;
+        }
+
+        self.dialogs.borrow_mut().insert(request_id, dialog);
+    }
+
+    pub(crate) fn show(&self, dialog: SimpleDialog) {
+        let (dialog_type, message, default_text) = match &dialog {
+            SimpleDialog::Alert { message, .. } => {
+                (DialogType::Alert, message.clone(), String::new())
+            }
+            SimpleDialog::Confirm { message, .. } => {
+                (DialogType::Confirm, message.clone(), String::new())
+            }
+            SimpleDialog::Prompt {
+                message, default, ..
+            } => (DialogType::Prompt, message.clone(), default.clone()),
+        };
+        self.add(
+            dialog_type,
+            message,
+            default_text,
+            PendingDialog::Simple(dialog),
+        );
+    }
+
+    /// Asks whether to leave a page that has a `beforeunload` handler.
+    pub(crate) fn confirm_unload(&self, unload_request: AllowOrDenyRequest) {
+        self.add(
+            DialogType::BeforeUnload,
+            String::new(),
+            String::new(),
+            PendingDialog::BeforeUnload(unload_request),
+        );
+    }
+
+    pub(crate) fn respond(&self, request_id: u64, confirmed: bool, text: String) {
+        let dialog = self.dialogs.borrow_mut().remove(&request_id);
+        match dialog {
+            Some(dialog) => dialog.respond(confirmed, text),
+            None => log::warn!("Got a response for unknown dialog {request_id}"),
+        }
+    }
+}
@@ -0,0 +1,574 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::rc::{Rc, Weak};
+use std::sync::mpsc::Sender;
+
+use bytes::Bytes;
+use cookie::Cookie;
+use http::Request;
+use http::header::{
+    CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, HeaderMap, HeaderName, HeaderValue,
+    LOCATION, SET_COOKIE,
+};
+use http::response::Parts;
+use http_body_util::{BodyExt, Empty};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use net_traits::CookieSource;
+use servo::Servo;
+use servo_gtk::proto_ipc::{
+    DownloadFailed, DownloadFinished, DownloadProgress, DownloadResponse, servo_event,
+};
+use tokio::runtime::Runtime;
+use tokio::task::AbortHandle;
+use url::Url;
+
+use crate::{RunnerMessage, send_event};
+
+/// How many redirects a download follows before giving up, as Servo does
+/// for page loads.
+const MAX_REDIRECTS: u32 = 20;
+
+/// What the network thread tells the main loop about a download.
+pub(crate) struct DownloadMessage {
+    download_id: u64,
+    /// The redirects followed before the request this is about, telling
+    /// the messages of a redirected request apart from the current ones
+    redirects: u32,
+    event: FetchEvent,
+}
+
+enum FetchEvent {
+    Response(Parts),
+    Data(Bytes),
+    Done(Result<(), String>),
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%'
+            && let Some(byte) = value
+                .get(index + 1..index + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Picks a file name from the `Content-Disposition` header, falling back to
+/// the last segment of the URL.
+fn suggested_filename(disposition: &str, url: &str) -> String {
+    let mut filename = None;
+    for parameter in disposition.split(';').map(str::trim) {
+        let Some((name, value)) = parameter.split_once('=') else {
+            continue;
+        };
+        match name.trim().to_ascii_lowercase().as_str() {
+            // RFC 5987 encoded names take precedence
+            "filename*" => {
+                let value = value.trim();
+                let value = value.rsplit_once('\'').map_or(value, |(_, value)| value);
+                filename = Some(percent_decode(value));
+                break;
+            }
+            "filename" => filename = Some(value.trim().trim_matches('"').to_string()),
+            _ => {}
+        }
+    }
+
+    let filename = filename.or_else(|| {
+        Url::parse(url)
+            .ok()?
+            .path_segments()?
+            .next_back()
+            .map(percent_decode)
+    });
+
+    // Never let the server pick a directory
+    let filename = filename
+        .as_deref()
+        .and_then(|filename| filename.rsplit(['/', '\\']).next())
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    if filename.is_empty() || filename == "." || filename == ".." {
+        "download".to_string()
+    } else {
+        filename
+    }
+}
+
+fn header_str(headers: &HeaderMap, name: HeaderName) -> &str {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// The requests the runner makes for downloads, with the cookies of Servo.
+///
+/// They run on a thread of their own, which streams the responses back to
+/// the main loop as [`DownloadMessage`]s.
+pub(crate) struct Network {
+    servo: Weak<Servo>,
+    runtime: Runtime,
+    client: Client<HttpsConnector<HttpConnector>, Empty<Bytes>>,
+    sender: Sender<RunnerMessage>,
+}
+
+impl Network {
+    pub(crate) fn new(servo: Weak<Servo>, sender: Sender<RunnerMessage>) -> io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("Network")
+            .enable_all()
+            .build()?;
+        // The system roots, like the rest of the desktop
+        let connector = HttpsConnectorBuilder::new()
+            .with_native_roots()?
+            .https_or_http()
+            .enable_http1()
+            .enable_http2()
+            .build();
+        let client = Client::builder(TokioExecutor::new()).build(connector);
+        Ok(Self {
+            servo,
+            runtime,
+            client,
+            sender,
+        })
+    }
+
+    /// Sends a GET request to `url`, and streams the response to the main
+    /// loop as messages of `webview_id` about `download_id`.
+    fn fetch(
+        &self,
+        webview_id: u64,
+        download_id: u64,
+        redirects: u32,
+        url: &Url,
+    ) -> Result<AbortHandle, String> {
+        let mut request = Request::get(url.as_str())
+            .body(Empty::new())
+            .map_err(|err| err.to_string())?;
+        if let Some(servo) = self.servo.upgrade() {
+            let cookies: Vec<String> = servo
+                .site_data_manager()
+                .cookies_for_url(url.clone(), CookieSource::HTTP)
+                .iter()
+                .map(|cookie| format!("{}={}", cookie.name(), cookie.value()))
+                .collect();
+            if !cookies.is_empty()
+                && let Ok(cookie_header) = HeaderValue::from_str(&cookies.join("; "))
+            {
+                request.headers_mut().insert(COOKIE, cookie_header);
+            }
+        }
+
+        let client = self.client.clone();
+        let sender = self.sender.clone();
+        let send = move |event| {
+            let message = DownloadMessage {
+                download_id,
+                redirects,
+                event,
+            };
+            sender
+                .send(RunnerMessage::Download(webview_id, message))
+                .is_ok()
+        };
+        let task = self.runtime.spawn(async move {
+            let response = match client.request(request).await {
+                Ok(response) => response,
+                Err(err) => {
+                    send(FetchEvent::Done(Err(err.to_string())));
+                    return;
+                }
+            };
+            let (parts, mut body) = response.into_parts();
+            if !send(FetchEvent::Response(parts)) {
+                return;
+            }
+            while let Some(frame) = body.frame().await {
+                match frame {
+                    Ok(frame) => {
+                        if let Ok(data) = frame.into_data()
+                            && !send(FetchEvent::Data(data))
+                        {
+                            return;
+                        }
+                    }
+                    Err(err) => {
+                        send(FetchEvent::Done(Err(err.to_string())));
+                        return;
+                    }
+                }
+            }
+            send(FetchEvent::Done(Ok(())));
+        });
+        Ok(task.abort_handle())
+    }
+
+    /// Keeps the cookies a response to `url` sets, as Servo would have.
+    fn store_cookies(&self, url: &Url, headers: &HeaderMap) {
+        let Some(servo) = self.servo.upgrade() else {
+            return;
+        };
+        for value in headers.get_all(SET_COOKIE) {
+            if let Some(cookie) = value
+                .to_str()
+                .ok()
+                .and_then(|value| Cookie::parse(value.to_string()).ok())
+            {
+                servo
+                    .site_data_manager()
+                    .set_cookie_for_url(url.clone(), cookie);
+            }
+        }
+    }
+}
+
+struct Download {
+    url: Url,
+    /// The redirects followed to get to `url`
+    redirects: u32,
+    abort_handle: AbortHandle,
+    /// Whether the whole response has been received
+    fetched: bool,
+    received_bytes: u64,
+    destination: Option<(PathBuf, File)>,
+    /// Data received before the widget picked a destination
+    pending_data: Vec<u8>,
+}
+
+/// The downloads the widget asks for, which Servo has no support for.
+///
+/// The runner fetches them itself, following redirects, and writes them to
+/// the destination the widget picks. Page loads are left to Servo.
+pub(crate) struct Downloads {
+    webview_id: u64,
+    network: Rc<Network>,
+    downloads: RefCell<HashMap<u64, Download>>,
+}
+
+impl Downloads {
+    pub(crate) fn new(webview_id: u64, network: Rc<Network>) -> Self {
+        Self {
+            webview_id,
+            network,
+            downloads: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn start(&self, download_id: u64, url: String) {
+        log::info!("Starting download {download_id} of {url}");
+        let download = Url::parse(&url)
+            .map_err(|err| format!("Invalid URL {url}: {err}"))
+            .and_then(|url| {
+                let abort_handle = self.network.fetch(self.webview_id, download_id, 0, &url)?;
+                Ok(Download {
+                    url,
+                    redirects: 0,
+                    abort_handle,
+                    fetched: false,
+                    received_bytes: 0,
+                    destination: None,
+                    pending_data: Vec::new(),
+                })
+            });
+        match download {
+            Ok(download) => {
+                self.downloads.borrow_mut().insert(download_id, download);
+            }
+            Err(error) => {
+                log::warn!("Download {download_id} failed: {error}");
+                let event =
+                    servo_event::Event::DownloadFailed(DownloadFailed { download_id, error });
+                let _ = send_event(self.webview_id, event);
+            }
+        }
+    }
+
+    pub(crate) fn handle_message(&self, message: DownloadMessage) {
+        let DownloadMessage {
+            download_id,
+            redirects,
+            event,
+        } = message;
+        let current = self
+            .downloads
+            .borrow()
+            .get(&download_id)
+            .is_some_and(|download| download.redirects == redirects);
+        if !current {
+            // Cancelled or redirected meanwhile
+            return;
+        }
+
+        match event {
+            FetchEvent::Response(response) => self.responded(download_id, response),
+            FetchEvent::Data(data) => self.received(download_id, data),
+            FetchEvent::Done(Ok(())) => self.fetched(download_id),
+            FetchEvent::Done(Err(error)) => self.fail(download_id, error),
+        }
+    }
+
+    fn responded(&self, download_id: u64, response: Parts) {
+        let url = match self.downloads.borrow().get(&download_id) {
+            Some(download) => download.url.clone(),
+            None => return,
+        };
+        self.network.store_cookies(&url, &response.headers);
+
+        if response.status.is_redirection() {
+            if let Err(error) = self.redirect(download_id, &url, &response.headers) {
+                self.fail(download_id, error);
+            }
+        } else if !response.status.is_success() {
+            self.fail(download_id, format!("HTTP error {}", response.status));
+        } else {
+            self.send_response(download_id, &url, &response);
+        }
+    }
+
+    /// Follows a redirect from `url` to the location in `headers`, dropping
+    /// the request that got it.
+    fn redirect(&self, download_id: u64, url: &Url, headers: &HeaderMap) -> Result<(), String> {
+        let location = header_str(headers, LOCATION);
+        if location.is_empty() {
+            return Err("Redirect without a location".to_string());
+        }
+        let location = url
+            .join(location)
+            .map_err(|err| format!("Invalid redirect to {location}: {err}"))?;
+
+        let mut downloads = self.downloads.borrow_mut();
+        let Some(download) = downloads.get_mut(&download_id) else {
+            return Ok(());
+        };
+        if download.redirects == MAX_REDIRECTS {
+            return Err("Too many redirects".to_string());
+        }
+        log::info!("Download {download_id} redirected to {location}");
+        download.abort_handle.abort();
+        download.redirects += 1;
+        download.abort_handle =
+            self.network
+                .fetch(self.webview_id, download_id, download.redirects, &location)?;
+        download.url = location;
+        Ok(())
+    }
+
+    fn send_response(&self, download_id: u64, url: &Url, response: &Parts) {
+        let total_bytes = header_str(&response.headers, CONTENT_LENGTH)
+            .parse()
+            .unwrap_or(0);
+        let event = servo_event::Event::DownloadResponse(DownloadResponse {
+            download_id,
+            suggested_filename: suggested_filename(
+                header_str(&response.headers, CONTENT_DISPOSITION),
+                url.as_str(),
+            ),
+            mime_type: header_str(&response.headers, CONTENT_TYPE).to_string(),
+            total_bytes,
+        });
+        let _ = send_event(self.webview_id, event);
+    }
+
+    fn received(&self, download_id: u64, data: Bytes) {
+        let mut downloads = self.downloads.borrow_mut();
+        let Some(download) = downloads.get_mut(&download_id) else {
+            return;
+        };
+
+        download.received_bytes += data.len() as u64;
+        let written = match download.destination.as_mut() {
+            Some((_, file)) => file.write_all(&data),
+            None => {
+                download.pending_data.extend_from_slice(&data);
+                Ok(())
+            }
+        };
+        let received_bytes = download.received_bytes;
+        drop(downloads);
+        if let Err(err) = written {
+            self.fail(download_id, err.to_string());
+            return;
+        }
+
+        let event = servo_event::Event::DownloadProgress(DownloadProgress {
+            download_id,
+            received_bytes,
+        });
+        let _ = send_event(self.webview_id, event);
+    }
+
+    fn fetched(&self, download_id: u64) {
+        let mut downloads = self.downloads.borrow_mut();
+        let Some(download) = downloads.get_mut(&download_id) else {
+            return;
+        };
+        download.fetched = true;
+        let has_destination = download.destination.is_some();
+        drop(downloads);
+        if has_destination {
+            self.finish(download_id);
+        }
+    }
+
+    /// Starts writing the download to `path`, where the data received so far
+    /// goes first.
+    pub(crate) fn set_destination(&self, download_id: u64, path: String) {
+        let mut downloads = self.downloads.borrow_mut();
+        let Some(download) = downloads.get_mut(&download_id) else {
+            return;
+        };
+        if download.destination.is_some() {
+            log::warn!("Download {download_id} already has a destination");
+            return;
+        }
+
+        // The widget makes sure the path is the app's to write to
+        let path = PathBuf::from(path);
+        let written = File::create(&path).and_then(|mut file| {
+            file.write_all(&download.pending_data)?;
+            Ok(file)
+        });
+        let file = match written {
+            Ok(file) => file,
+            Err(err) => {
+                drop(downloads);
+                self.fail(download_id, err.to_string());
+                return;
+            }
+        };
+        log::info!("Writing download {download_id} to {}", path.display());
+        download.pending_data = Vec::new();
+        download.destination = Some((path, file));
+
+        let fetched = download.fetched;
+        drop(downloads);
+        if fetched {
+            self.finish(download_id);
+        }
+    }
+
+    pub(crate) fn cancel(&self, download_id: u64) {
+        let download = self.downloads.borrow_mut().remove(&download_id);
+        if let Some(download) = download {
+            log::info!("Cancelling download {download_id}");
+            download.abort_handle.abort();
+            if let Some((path, _)) = download.destination {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    fn finish(&self, download_id: u64) {
+        let download = self.downloads.borrow_mut().remove(&download_id);
+        let Some(download) = download else {
+            return;
+        };
+        if let Some((_, file)) = download.destination
+            && let Err(err) = file.sync_all()
+        {
+            log::warn!("Failed to sync download {download_id}: {err}");
+        }
+        let event = servo_event::Event::DownloadFinished(DownloadFinished { download_id });
+        let _ = send_event(self.webview_id, event);
+    }
+
+    fn fail(&self, download_id: u64, error: String) {
+        let download = self.downloads.borrow_mut().remove(&download_id);
+        let Some(download) = download else {
+            return;
+        };
+        log::warn!("Download {download_id} failed: {error}");
+        download.abort_handle.abort();
+        if let Some((path, _)) = download.destination {
+            let _ = fs::remove_file(path);
+        }
+        let event = servo_event::Event::DownloadFailed(DownloadFailed { download_id, error });
+        let _ = send_event(self.webview_id, event);
+    }
+}
+
+impl Drop for Downloads {
+    fn drop(&mut self) {
+        for download in self.downloads.get_mut().values() {
+            download.abort_handle.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filename_comes_from_the_disposition() {
+        assert_eq!(
+            suggested_filename(
+                "attachment; filename=\"report.csv\"",
+                "https://example.com/export"
+            ),
+            "report.csv"
+        );
+        assert_eq!(
+            suggested_filename(
+                "attachment; filename=\"fallback.csv\"; filename*=UTF-8''r%C3%A9sum%C3%A9.csv",
+                "https://example.com/export"
+            ),
+            "résumé.csv"
+        );
+    }
+
+    #[test]
+    fn filename_falls_back_to_the_url() {
+        assert_eq!(
+            suggested_filename("attachment", "https://example.com/files/data%201.csv?x=1"),
+            "data 1.csv"
+        );
+        assert_eq!(suggested_filename("", "https://example.com/"), "download");
+    }
+
+    #[test]
+    fn filename_never_picks_a_directory() {
+        assert_eq!(
+            suggested_filename(
+                "attachment; filename=\"../../.bashrc\"",
+                "https://example.com/"
+            ),
+            ".bashrc"
+        );
+        assert_eq!(
+            suggested_filename(
+                "attachment; filename=\"C:\\evil.exe\"",
+                "https://example.com/"
+            ),
+            "evil.exe"
+        );
+        assert_eq!(
+            suggested_filename("attachment; filename=\"..\"", "https://example.com/"),
+            "download"
+        );
+    }
+}
@@ -0,0 +1,87 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use embedder_traits::FilterPattern;
+use servo_gtk::proto_ipc::{FileChooserRequest, servo_event};
+
+use crate::send_event;
+
+type FileChooserReply = Box<dyn FnOnce(Option<Vec<PathBuf>>)>;
+
+/// File selections waiting for the user to pick files in the widget.
+pub(crate) struct PendingFileChoosers {
+    webview_id: u64,
+    next_request_id: Cell<u64>,
+    replies: RefCell<HashMap<u64, FileChooserReply>>,
+    // Servo doesn't suggest a directory, so start from where the previous
+    // files were picked
+    last_directory: RefCell<Option<PathBuf>>,
+}
+
+impl PendingFileChoosers {
+    pub(crate) fn new(webview_id: u64) -> Self {
+        Self {
+            webview_id,
+            next_request_id: Cell::new(0),
+            replies: RefCell::new(HashMap::new()),
+            last_directory: RefCell::new(None),
+        }
+    }
+
+    pub(crate) fn request(
+        &self,
+        filter_patterns: Vec<FilterPattern>,
+        multiple: bool,
+        reply: impl FnOnce(Option<Vec<PathBuf>>) + 'static,
+    ) {
+        let request_id = self.next_request_id.get();
+        self.next_request_id.set(request_id + 1);
+
+        let directory = self
+            .last_directory
+            .borrow()
+            .as_ref()
+            .map(|directory| directory.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let event = servo_event::Event::FileChooserRequest(FileChooserRequest {
+            request_id,
+            accept: filter_patterns
+                .into_iter()
+                .map(|filter_pattern| filter_pattern.0)
+                .collect(),
+            multiple,
+            directory,
+        });
+        if send_event(self.webview_id, event).is_err() {
+            reply(None);
+            return;
+        }
+
+        self.replies
+            .borrow_mut()
+            .insert(request_id, Box::new(reply));
+    }
+
+    pub(crate) fn respond(&self, request_id: u64, paths: Vec<String>) {
+        let Some(reply) = self.replies.borrow_mut().remove(&request_id) else {
+            log::warn!("Got a response for unknown file chooser {request_id}");
+            return;
+        };
+
+        if paths.is_empty() {
+            reply(None);
+            return;
+        }
+
+        let paths: Vec<PathBuf> = paths.into_iter().map(PathBuf::from).collect();
+        if let Some(directory) = paths[0].parent() {
+            self.last_directory.replace(Some(directory.to_path_buf()));
+        }
+        reply(Some(paths));
+    }
+}
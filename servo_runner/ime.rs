@@ -0,0 +1,149 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::Cell;
+
+use embedder_traits::{ImeEvent, InputMethodType};
+use keyboard_types::{
+    Code, CompositionEvent, CompositionState, Key, KeyState, Location, Modifiers,
+};
+use servo::{DeviceIntRect, InputEvent, KeyboardEvent, WebView};
+use servo_gtk::proto_ipc::{ImeState, InputPurpose, servo_event};
+
+use crate::send_event;
+
+/// Deletes characters around the cursor of the focused field, counting
+/// them the way the input method does rather than in UTF-16 code units.
+const DELETE_SURROUNDING_SCRIPT: &str = r#"(function (offset, length) {
+    const element = document.activeElement;
+    if (!element || typeof element.selectionStart !== "number") {
+        return;
+    }
+    const characters = Array.from(element.value);
+    const cursor = Array.from(element.value.slice(0, element.selectionStart)).length;
+    const start = Math.min(Math.max(cursor + offset, 0), characters.length);
+    const end = Math.min(start + length, characters.length);
+    element.setRangeText(
+        "",
+        characters.slice(0, start).join("").length,
+        characters.slice(0, end).join("").length,
+        "end",
+    );
+    element.dispatchEvent(new InputEvent("input", {
+        bubbles: true,
+        inputType: "deleteContentBackward",
+    }));
+})"#;
+
+fn convert_input_method_type(input_method_type: InputMethodType) -> InputPurpose {
+    match input_method_type {
+        InputMethodType::Email => InputPurpose::Email,
+        InputMethodType::Number => InputPurpose::Number,
+        InputMethodType::Password => InputPurpose::Password,
+        InputMethodType::Tel => InputPurpose::Phone,
+        InputMethodType::Url => InputPurpose::Url,
+        _ => InputPurpose::FreeForm,
+    }
+}
+
+/// Text composed by the input method of the widget, turned into the
+/// composition events of the focused field.
+///
+/// The widget sends the text committed while filtering a key press with
+/// the key itself, so the commits here either end a composition or come
+/// from elsewhere, like an on-screen keyboard.
+pub(crate) struct Ime {
+    webview_id: u64,
+    composing: Cell<bool>,
+}
+
+impl Ime {
+    pub(crate) fn new(webview_id: u64) -> Self {
+        Self {
+            webview_id,
+            composing: Cell::new(false),
+        }
+    }
+
+    fn notify_composition(&self, webview: &WebView, state: CompositionState, data: String) {
+        let event = ImeEvent::Composition(CompositionEvent { state, data });
+        webview.notify_input_event(InputEvent::Ime(event));
+    }
+
+    /// Tells the widget a text field got the focus, at `position`.
+    pub(crate) fn show(
+        &self,
+        input_method_type: InputMethodType,
+        text: Option<(String, i32)>,
+        position: DeviceIntRect,
+    ) {
+        let (surrounding_text, cursor_index) = text.unwrap_or_default();
+        let event = servo_event::Event::ImeState(ImeState {
+            active: true,
+            purpose: convert_input_method_type(input_method_type) as i32,
+            surrounding_text,
+            cursor_index,
+            x: position.min.x,
+            y: position.min.y,
+            width: position.width(),
+            height: position.height(),
+        });
+        let _ = send_event(self.webview_id, event);
+    }
+
+    pub(crate) fn hide(&self) {
+        // Whatever was being composed went away with the field
+        self.composing.set(false);
+        let event = servo_event::Event::ImeState(ImeState {
+            active: false,
+            ..Default::default()
+        });
+        let _ = send_event(self.webview_id, event);
+    }
+
+    pub(crate) fn preedit(&self, webview: &WebView, text: String) {
+        if !self.composing.get() {
+            if text.is_empty() {
+                return;
+            }
+            self.composing.set(true);
+            self.notify_composition(webview, CompositionState::Start, String::new());
+        }
+        // An empty preedit only clears the composed text, the composition
+        // goes on until something is committed
+        self.notify_composition(webview, CompositionState::Update, text);
+    }
+
+    pub(crate) fn commit(&self, webview: &WebView, text: String) {
+        if self.composing.replace(false) {
+            self.notify_composition(webview, CompositionState::End, text);
+            return;
+        }
+
+        // Typed without composing, so it reads as a key carrying the text
+        for state in [KeyState::Down, KeyState::Up] {
+            let event = KeyboardEvent::new_without_event(
+                state,
+                Key::Character(text.clone()),
+                Code::Unidentified,
+                Location::Standard,
+                Modifiers::empty(),
+                false,
+                false,
+            );
+            webview.notify_input_event(InputEvent::Keyboard(event));
+        }
+    }
+
+    pub(crate) fn delete_surrounding(&self, webview: &WebView, offset: i32, length: u32) {
+        webview.evaluate_javascript(
+            format!("{DELETE_SURROUNDING_SCRIPT}({offset}, {length})"),
+            |result| {
+                if let Err(err) = result {
+                    log::warn!("Failed to delete text around the cursor: {err:?}");
+                }
+            },
+        );
+    }
+}
@@ -0,0 +1,229 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use embedder_traits::WebResourceResponse;
+use http::StatusCode;
+use http::header::{CONTENT_TYPE, HeaderMap, HeaderValue};
+use net::fetch::methods::{DoneChannel, FetchContext};
+use net::protocols::ProtocolHandler;
+use net_traits::http_status::HttpStatus;
+use net_traits::request::Request;
+use net_traits::response::{Response, ResponseBody};
+use net_traits::{NetworkError, ResourceFetchTiming};
+use servo::{WebResourceLoad, WebView, WebViewId};
+use url::Url;
+
+/// Scheme of the documents loaded without a base URI.
+pub(crate) const LOCAL_CONTENT_SCHEME: &str = "servo-gtk";
+
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte))
+}
+
+/// Builds the `Content-Type` of a document from its MIME type and
+/// encoding, or returns `None` if either isn't valid.
+///
+/// The MIME type may come with parameters already, in which case a
+/// `charset` among them wins over `encoding`.
+fn content_type(mime_type: &str, encoding: &str) -> Option<String> {
+    let mime_type = mime_type.trim();
+    let (essence, parameters) = mime_type.split_once(';').unwrap_or((mime_type, ""));
+    let (top_level, subtype) = essence.trim().split_once('/')?;
+    if !is_token(top_level) || !is_token(subtype) {
+        return None;
+    }
+    if mime_type.chars().any(|character| character.is_control()) {
+        return None;
+    }
+
+    let has_charset = parameters.split(';').any(|parameter| {
+        parameter
+            .split_once('=')
+            .is_some_and(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+    });
+    if encoding.is_empty() || has_charset {
+        return Some(mime_type.to_string());
+    }
+    if !is_token(encoding) {
+        return None;
+    }
+    Some(format!("{mime_type}; charset={encoding}"))
+}
+
+struct LocalContent {
+    url: Url,
+    content_type: String,
+    data: Vec<u8>,
+}
+
+/// Documents the widget hands over as bytes, served to Servo under their
+/// base URI as if they came from there.
+///
+/// Servo asks the runner about every request before fetching it, so the
+/// main frame loads of a view at the URL of its document get the document
+/// instead. Documents without a base URI get a URL of their own scheme.
+/// A view keeps its document for reloads until it navigates elsewhere.
+#[derive(Clone, Default)]
+pub(crate) struct LocalContents {
+    // Read from the network threads for the documents of the local scheme
+    contents: Arc<Mutex<HashMap<WebViewId, LocalContent>>>,
+}
+
+impl LocalContents {
+    /// Loads `data` in `webview` under `base_uri`. Returns why it can't be
+    /// loaded otherwise.
+    pub(crate) fn load(
+        &self,
+        webview: &WebView,
+        data: Vec<u8>,
+        mime_type: &str,
+        encoding: &str,
+        base_uri: &str,
+    ) -> Result<(), String> {
+        let url = if base_uri.is_empty() {
+            Url::parse(&format!("{LOCAL_CONTENT_SCHEME}:document"))
+        } else {
+            Url::parse(base_uri)
+        }
+        .map_err(|err| format!("Invalid base URI {base_uri}: {err}"))?;
+        let content_type = content_type(mime_type, encoding)
+            .ok_or_else(|| format!("Invalid MIME type {mime_type} or encoding {encoding}"))?;
+
+        self.contents.lock().unwrap().insert(
+            webview.id(),
+            LocalContent {
+                url: url.clone(),
+                content_type,
+                data,
+            },
+        );
+        webview.load(url);
+        Ok(())
+    }
+
+    /// Stops serving the document of `webview`, which loads something else.
+    pub(crate) fn forget(&self, webview: &WebView) {
+        self.contents.lock().unwrap().remove(&webview.id());
+    }
+
+    /// Answers `load` with the document of `webview` if it is a main frame
+    /// load of its URL, and lets it through otherwise.
+    pub(crate) fn intercept(&self, webview: &WebView, load: WebResourceLoad) {
+        let request = load.request();
+        if !request.is_for_main_frame {
+            return;
+        }
+
+        let mut contents = self.contents.lock().unwrap();
+        let Some(content) = contents.get(&webview.id()) else {
+            return;
+        };
+        if content.url != request.url {
+            // Navigated away
+            contents.remove(&webview.id());
+            return;
+        }
+
+        let mut headers = HeaderMap::new();
+        if let Ok(content_type) = HeaderValue::from_str(&content.content_type) {
+            headers.insert(CONTENT_TYPE, content_type);
+        }
+        let response = WebResourceResponse::new(request.url.clone())
+            .headers(headers)
+            .status_code(StatusCode::OK);
+        let data = content.data.clone();
+        drop(contents);
+
+        let intercepted = load.intercept(response);
+        intercepted.send_body_data(data);
+        intercepted.finish();
+    }
+
+    fn response(&self, request: &Request) -> Response {
+        let url = request.current_url();
+        let contents = self.contents.lock().unwrap();
+        let Some(content) = request
+            .target_webview_id
+            .and_then(|webview_id| contents.get(&webview_id))
+            .filter(|content| content.url == *url.as_url())
+        else {
+            return Response::network_error(NetworkError::Internal(
+                "Nothing was loaded at this URL".to_string(),
+            ));
+        };
+
+        let mut response = Response::new(url, ResourceFetchTiming::new(request.timing_type()));
+        response.status = HttpStatus::new_raw(200, b"OK".to_vec());
+        if let Ok(content_type) = HeaderValue::from_str(&content.content_type) {
+            response.headers.insert(CONTENT_TYPE, content_type);
+        }
+        *response.body.lock().unwrap() = ResponseBody::Done(content.data.clone());
+        response
+    }
+}
+
+/// Serves the documents loaded without a base URI, under
+/// [`LOCAL_CONTENT_SCHEME`].
+pub(crate) struct LocalContentHandler {
+    contents: LocalContents,
+}
+
+impl LocalContentHandler {
+    pub(crate) fn new(contents: LocalContents) -> Self {
+        Self { contents }
+    }
+}
+
+impl ProtocolHandler for LocalContentHandler {
+    fn load(
+        &self,
+        request: &mut Request,
+        _done_chan: &mut DoneChannel,
+        _context: &FetchContext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send>> {
+        let response = self.contents.response(request);
+        Box::pin(async move { response })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding_becomes_the_charset() {
+        assert_eq!(
+            content_type("text/html", "UTF-8").as_deref(),
+            Some("text/html; charset=UTF-8")
+        );
+        assert_eq!(
+            content_type("application/xhtml+xml", "").as_deref(),
+            Some("application/xhtml+xml")
+        );
+    }
+
+    #[test]
+    fn charset_of_the_mime_type_wins() {
+        assert_eq!(
+            content_type("text/html; charset=UTF-16", "UTF-8").as_deref(),
+            Some("text/html; charset=UTF-16")
+        );
+    }
+
+    #[test]
+    fn invalid_types_are_rejected() {
+        assert_eq!(content_type("text", "UTF-8"), None);
+        assert_eq!(content_type("text/html\r\nX-Header: 1", "UTF-8"), None);
+        assert_eq!(content_type("text/ht ml", "UTF-8"), None);
+        assert_eq!(content_type("text/html", "UTF-8;base64"), None);
+    }
+}
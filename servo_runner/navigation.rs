@@ -0,0 +1,109 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use servo::NavigationRequest;
+use servo_gtk::proto_ipc::{NavigationType, servo_event};
+
+use crate::timer::Timers;
+use crate::{RunnerMessage, send_event};
+
+/// How long a navigation waits for the widget before being allowed.
+const NAVIGATION_POLICY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Navigations starting this soon after a click are considered caused by it.
+const CLICK_NAVIGATION_WINDOW: Duration = Duration::from_secs(1);
+
+/// Navigations waiting for the widget's policy decision.
+pub(crate) struct PendingNavigations {
+    webview_id: u64,
+    timers: Rc<Timers>,
+    next_request_id: Cell<u64>,
+    requests: RefCell<HashMap<u64, NavigationRequest>>,
+    last_click: Cell<Option<(u32, Instant)>>,
+    modifiers: Cell<u32>,
+}
+
+impl PendingNavigations {
+    pub(crate) fn new(webview_id: u64, timers: Rc<Timers>) -> Self {
+        Self {
+            webview_id,
+            timers,
+            next_request_id: Cell::new(0),
+            requests: RefCell::new(HashMap::new()),
+            last_click: Cell::new(None),
+            modifiers: Cell::new(0),
+        }
+    }
+
+    pub(crate) fn note_click(&self, button: u32) {
+        self.last_click.set(Some((button, Instant::now())));
+    }
+
+    pub(crate) fn note_modifiers(&self, modifiers: u32) {
+        self.modifiers.set(modifiers);
+    }
+
+    /// Asks the widget about `navigation_request`, which stays blocked until
+    /// `decide` is called or the timeout expires.
+    pub(crate) fn request(&self, navigation_request: NavigationRequest) {
+        let request_id = self.next_request_id.get();
+        self.next_request_id.set(request_id + 1);
+
+        // Servo doesn't tell what caused the navigation, so guess it from
+        // the last click
+        let (navigation_type, button) = match self.last_click.take() {
+            Some((button, time)) if time.elapsed() < CLICK_NAVIGATION_WINDOW => {
+                (NavigationType::LinkClicked, button)
+            }
+            _ => (NavigationType::Other, 0),
+        };
+
+        let event =
+            servo_event::Event::NavigationRequest(servo_gtk::proto_ipc::NavigationRequest {
+                request_id,
+                url: navigation_request.url.to_string(),
+                navigation_type: navigation_type as i32,
+                button,
+                modifiers: self.modifiers.get(),
+            });
+        if send_event(self.webview_id, event).is_err() {
+            navigation_request.allow();
+            return;
+        }
+
+        self.requests
+            .borrow_mut()
+            .insert(request_id, navigation_request);
+
+        self.timers.schedule(
+            NAVIGATION_POLICY_TIMEOUT,
+            RunnerMessage::NavigationTimeout {
+                webview_id: self.webview_id,
+                request_id,
+            },
+        );
+    }
+
+    pub(crate) fn decide(&self, request_id: u64, allow: bool) {
+        let Some(navigation_request) = self.requests.borrow_mut().remove(&request_id) else {
+            return;
+        };
+
+        log::debug!(
+            "Navigation to {} {}",
+            navigation_request.url,
+            if allow { "allowed" } else { "ignored" }
+        );
+        if allow {
+            navigation_request.allow();
+        } else {
+            navigation_request.deny();
+        }
+    }
+}
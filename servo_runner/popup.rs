@@ -0,0 +1,95 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use dpi::PhysicalSize;
+use servo::{Servo, SoftwareRenderingContext, WebView, WebViewBuilder};
+use servo_gtk::frame_buffer::INITIAL_FRAME_SIZE;
+use servo_gtk::proto_ipc::{CreateRequest, servo_event};
+
+use crate::send_event;
+
+/// A web view opened by a page, waiting for a widget to show it.
+pub(crate) struct Popup {
+    pub(crate) webview: WebView,
+    pub(crate) rendering_context: Rc<SoftwareRenderingContext>,
+    opener_webview_id: u64,
+}
+
+/// Web views opened through `window.open` and `target=_blank` links.
+///
+/// Servo wants the new web view right away, so it is created before the
+/// widget is asked about it, and closed again if the widget doesn't want
+/// it.
+pub(crate) struct PendingPopups {
+    servo: Weak<Servo>,
+    next_request_id: Cell<u64>,
+    popups: RefCell<HashMap<u64, Popup>>,
+}
+
+impl PendingPopups {
+    pub(crate) fn new(servo: Weak<Servo>) -> Self {
+        Self {
+            servo,
+            next_request_id: Cell::new(0),
+            popups: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Opens an auxiliary web view for the page of `opener_webview_id`.
+    pub(crate) fn open(&self, opener_webview_id: u64) -> Option<WebView> {
+        let servo = self.servo.upgrade()?;
+        // The widget resizes it once it shows it
+        let (width, height) = INITIAL_FRAME_SIZE;
+        let rendering_context =
+            match SoftwareRenderingContext::new(PhysicalSize::new(width, height)) {
+                Ok(rendering_context) => Rc::new(rendering_context),
+                Err(err) => {
+                    log::error!("Failed to create a rendering context for a popup: {err:?}");
+                    return None;
+                }
+            };
+
+        let request_id = self.next_request_id.get();
+        self.next_request_id.set(request_id + 1);
+
+        let event = servo_event::Event::CreateRequest(CreateRequest { request_id });
+        if send_event(opener_webview_id, event).is_err() {
+            return None;
+        }
+
+        // Servo keeps the opener of auxiliary web views, so the new page
+        // gets its `window.opener`
+        let webview = WebViewBuilder::new_auxiliary(&servo, rendering_context.clone()).build();
+        self.popups.borrow_mut().insert(
+            request_id,
+            Popup {
+                webview: webview.clone(),
+                rendering_context,
+                opener_webview_id,
+            },
+        );
+        Some(webview)
+    }
+
+    /// Hands the popup over to the view taking it, dropping it closes it.
+    pub(crate) fn take(&self, request_id: u64) -> Option<Popup> {
+        let popup = self.popups.borrow_mut().remove(&request_id);
+        if popup.is_none() {
+            log::warn!("Got a response for unknown popup {request_id}");
+        }
+        popup
+    }
+
+    /// Closes the popups of `opener_webview_id`, which went away before
+    /// answering for them.
+    pub(crate) fn close_opened_by(&self, opener_webview_id: u64) {
+        self.popups
+            .borrow_mut()
+            .retain(|_, popup| popup.opener_webview_id != opener_webview_id);
+    }
+}
@@ -2,42 +2,137 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::io::{self, Read, Write};
+use std::ops::ControlFlow;
+use std::os::fd::{AsFd, FromRawFd, OwnedFd};
 use std::rc::Rc;
 
-use core::time::Duration;
 use dpi::PhysicalSize;
-use embedder_traits::{WebViewPoint, WebViewVector, resources};
+use embedder_traits::{
+    FilterPattern, InputMethodType, JSValue, SimpleDialog, WebViewPoint, WebViewVector, resources,
+};
 use euclid::Point2D;
+use image::RgbaImage;
+use ipc_channel::ipc::IpcSender;
 use keyboard_types::{Code, Key, KeyState, Location, Modifiers, NamedKey};
+use net::protocols::ProtocolRegistry;
 
+use servo::{
+    AllowOrDenyRequest, ContextMenu, EventLoopWaker, LoadStatus, NavigationRequest,
+    RenderingContext, SoftwareRenderingContext, WebResourceLoad, WebView, WebViewBuilder,
+    WebViewDelegate,
+};
 use servo::{
     DeviceIntRect, DeviceVector2D, InputEvent, KeyboardEvent, MouseButton, MouseButtonAction,
-    MouseButtonEvent, MouseMoveEvent, Scroll, ServoBuilder,
+    MouseButtonEvent, MouseMoveEvent, Scroll, Servo, ServoBuilder,
 };
-use servo::{RenderingContext, SoftwareRenderingContext, WebView, WebViewBuilder, WebViewDelegate};
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::mpsc;
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{Receiver, Sender};
 use std::thread;
 use url::Url;
 
+use servo_gtk::frame_buffer::{FRAME_SOCKET_FD, FrameBuffer, INITIAL_FRAME_SIZE};
 use servo_gtk::proto_ipc::{
-    CursorChanged, FrameReady, LogLevel, LogMessage, ServoAction, ServoEvent, servo_action,
-    servo_event,
+    Closed, CursorChanged, ENGINE_REVISION, FrameDamage, FrameReady, Hello, HistoryChanged,
+    JavaScriptResult, JsArray, JsObject, JsValue, KeyPress, KeyRelease, LoadEnd, LoadFailed,
+    LoadProgress, LoadStart, LogLevel, LogMessage, PROTOCOL_VERSION, Resize, ScrollPhase,
+    ScrollUnit, ServoAction, ServoEvent, TitleChanged, UrlChanged, features, java_script_result,
+    js_value, servo_action, servo_event,
 };
 
+mod context_menu;
+mod damage;
+mod dialog;
+mod download;
+mod file_chooser;
+mod ime;
+mod local_content;
+mod navigation;
+mod popup;
 mod resource_reader;
+mod snapshot;
+mod timer;
+mod uri_scheme;
+use context_menu::report_context_menu;
+use dialog::PendingDialogs;
+use download::{DownloadMessage, Downloads, Network};
+use file_chooser::PendingFileChoosers;
+use ime::Ime;
+use local_content::{LOCAL_CONTENT_SCHEME, LocalContentHandler, LocalContents};
+use navigation::PendingNavigations;
+use popup::PendingPopups;
 use resource_reader::ResourceReaderInstance;
+use snapshot::Snapshots;
+use timer::Timers;
+use uri_scheme::{PendingSchemeRequests, UriSchemeHandler};
+
+/// How far one wheel click scrolls, three lines of text like in other
+/// browsers.
+const WHEEL_CLICK_PIXELS: f64 = 48.0;
+
+/// The keys pressed or released for Servo to get the modifiers of pointer
+/// events, which it only takes from key events.
+const MODIFIER_KEYS: &[(Modifiers, NamedKey, Code, Location)] = &[
+    (
+        Modifiers::SHIFT,
+        NamedKey::Shift,
+        Code::ShiftLeft,
+        Location::Left,
+    ),
+    (
+        Modifiers::CONTROL,
+        NamedKey::Control,
+        Code::ControlLeft,
+        Location::Left,
+    ),
+    (Modifiers::ALT, NamedKey::Alt, Code::AltLeft, Location::Left),
+    (
+        Modifiers::META,
+        NamedKey::Meta,
+        Code::MetaLeft,
+        Location::Left,
+    ),
+    (
+        Modifiers::ALT_GRAPH,
+        NamedKey::AltGraph,
+        Code::AltRight,
+        Location::Standard,
+    ),
+];
+
+/// Everything the main loop can be woken up for.
+enum RunnerMessage {
+    Action(ServoAction),
+    Log(LogMessage),
+    NavigationTimeout {
+        webview_id: u64,
+        request_id: u64,
+    },
+    Download(u64, DownloadMessage),
+    /// The document size measured for a full-document snapshot
+    DocumentSize {
+        webview_id: u64,
+        capture_id: u64,
+        result: Result<JSValue, String>,
+    },
+    DocumentTimeout {
+        webview_id: u64,
+        capture_id: u64,
+    },
+    Wake,
+}
 
 struct EventLogger {
-    sender: std::sync::mpsc::Sender<LogMessage>,
+    sender: Sender<RunnerMessage>,
 }
 
 impl EventLogger {
-    fn new() -> (Self, std::sync::mpsc::Receiver<LogMessage>) {
-        let (sender, receiver) = std::sync::mpsc::channel();
-        (Self { sender }, receiver)
+    fn new(sender: Sender<RunnerMessage>) -> Self {
+        Self { sender }
     }
 }
 
@@ -60,49 +155,294 @@ impl log::Log for EventLogger {
             message: format!("{}", record.args()),
         };
 
-        let _ = self.sender.send(log_message);
+        let _ = self.sender.send(RunnerMessage::Log(log_message));
     }
 
     fn flush(&self) {}
 }
 
-fn send_event(event: ServoEvent) -> std::io::Result<()> {
+#[derive(Clone)]
+struct RunnerEventLoopWaker {
+    sender: Sender<RunnerMessage>,
+}
+
+impl EventLoopWaker for RunnerEventLoopWaker {
+    fn clone_box(&self) -> Box<dyn EventLoopWaker> {
+        Box::new(self.clone())
+    }
+
+    fn wake(&self) {
+        let _ = self.sender.send(RunnerMessage::Wake);
+    }
+}
+
+fn send_event(webview_id: u64, event: servo_event::Event) -> std::io::Result<()> {
+    let event = ServoEvent {
+        webview_id,
+        event: Some(event),
+    };
     let encoded = event.encode_to_vec();
     let len = (encoded.len() as u32).to_le_bytes();
-    io::stdout().write_all(&len)?;
-    io::stdout().write_all(&encoded)
+    // Events are also sent from Servo's threads, keep each one in one piece
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&len)?;
+    stdout.write_all(&encoded)
+}
+
+fn send_log_message(log_message: LogMessage) {
+    let event = servo_event::Event::LogMessage(log_message);
+    let _ = send_event(0, event);
 }
 
 struct ServoWebViewDelegate {
+    webview_id: u64,
     rendering_context: Rc<dyn RenderingContext>,
+    /// Shared with the delegates of the popups the view adopts
+    frame_buffer: RefCell<Rc<FrameBuffer>>,
+    /// The last frame sent to the widget
+    previous_frame: RefCell<Option<RgbaImage>>,
+    /// A newer frame waiting for the widget to release a slot
+    pending_frame: RefCell<Option<RgbaImage>>,
+    /// The load the widget asked for last, until it completes
+    load_id: Cell<u64>,
+    /// Whether Servo started loading since
+    load_started: Cell<bool>,
+    navigations: PendingNavigations,
+    dialogs: PendingDialogs,
+    file_choosers: PendingFileChoosers,
+    ime: Ime,
+    downloads: Downloads,
+    snapshots: Snapshots,
+    popups: Rc<PendingPopups>,
+    local_contents: LocalContents,
+    /// The [`features`] the widget supports
+    features: u64,
+    /// Where the last touchpad gesture began, which its scrolls and the
+    /// kinetic scrolling after it go to
+    scroll_origin: Cell<Option<(f64, f64)>>,
+    /// The modifiers of the last key event Servo got
+    modifiers: Cell<Modifiers>,
 }
 
 impl ServoWebViewDelegate {
-    fn new(rendering_context: Rc<dyn RenderingContext>) -> Self {
-        Self { rendering_context }
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        webview_id: u64,
+        rendering_context: Rc<dyn RenderingContext>,
+        frame_buffer: Rc<FrameBuffer>,
+        sender: Sender<RunnerMessage>,
+        timers: Rc<Timers>,
+        popups: Rc<PendingPopups>,
+        local_contents: LocalContents,
+        network: Rc<Network>,
+        features: u64,
+    ) -> Self {
+        Self {
+            webview_id,
+            rendering_context,
+            frame_buffer: RefCell::new(frame_buffer),
+            previous_frame: RefCell::new(None),
+            pending_frame: RefCell::new(None),
+            load_id: Cell::new(0),
+            load_started: Cell::new(false),
+            navigations: PendingNavigations::new(webview_id, timers.clone()),
+            downloads: Downloads::new(webview_id, network),
+            snapshots: Snapshots::new(webview_id, sender, timers),
+            dialogs: PendingDialogs::new(webview_id),
+            file_choosers: PendingFileChoosers::new(webview_id),
+            ime: Ime::new(webview_id),
+            popups,
+            local_contents,
+            features,
+            scroll_origin: Cell::new(None),
+            modifiers: Cell::new(Modifiers::empty()),
+        }
+    }
+
+    /// Renders the document enlarged for a snapshot and captures it.
+    fn capture_document(&self, webview: &WebView) {
+        let size = self.rendering_context.size2d().to_i32();
+        webview.paint();
+        self.rendering_context.present();
+        let image = self
+            .rendering_context
+            .read_to_image(DeviceIntRect::from_origin_and_size(Point2D::origin(), size));
+        self.snapshots.finish_document(webview, image);
+    }
+
+    fn document_measured(
+        &self,
+        webview: &WebView,
+        capture_id: u64,
+        result: Result<JSValue, String>,
+    ) {
+        if self.snapshots.document_measured(
+            webview,
+            capture_id,
+            self.rendering_context.size(),
+            result,
+        ) {
+            self.capture_document(webview);
+        }
+    }
+
+    /// Sends `rgba_image` to the widget, or keeps it until the widget
+    /// releases a slot.
+    fn send_frame(&self, rgba_image: RgbaImage) {
+        let width = rgba_image.width();
+        let height = rgba_image.height();
+        let stride = width * 4;
+
+        // Only ship the tiles that changed when the size is unchanged
+        let damage = self
+            .previous_frame
+            .borrow()
+            .as_ref()
+            .filter(|previous| previous.dimensions() == rgba_image.dimensions())
+            .map(|previous| {
+                damage::damaged_rects(
+                    previous.as_raw(),
+                    rgba_image.as_raw(),
+                    width,
+                    height,
+                    stride,
+                )
+            });
+        if damage.as_ref().is_some_and(|rects| rects.is_empty()) {
+            self.pending_frame.take();
+            return;
+        }
+
+        let frame_buffer = self.frame_buffer.borrow().clone();
+        match frame_buffer.write_frame(rgba_image.as_raw(), stride, height) {
+            Ok(Some(buffer_index)) => {
+                let frame_buffer_id = frame_buffer.id();
+                let event = match damage {
+                    Some(rects) => servo_event::Event::FrameDamage(FrameDamage {
+                        buffer_index,
+                        width,
+                        height,
+                        stride,
+                        rects,
+                        frame_buffer_id,
+                    }),
+                    None => servo_event::Event::FrameReady(FrameReady {
+                        width,
+                        height,
+                        buffer_index,
+                        stride,
+                        frame_buffer_id,
+                    }),
+                };
+                let _ = send_event(self.webview_id, event);
+                self.pending_frame.take();
+                self.previous_frame.replace(Some(rgba_image));
+            }
+            Ok(None) => {
+                log::debug!("The widget holds all frame slots, keeping the frame back");
+                self.pending_frame.replace(Some(rgba_image));
+            }
+            Err(err) => log::error!("Failed to write frame: {err}"),
+        }
+    }
+
+    /// Takes back the slot `buffer_index` of the frame buffer
+    /// `frame_buffer_id`, and fills it with the frame that was waiting for
+    /// it if any.
+    fn release_frame(&self, frame_buffer_id: u32, buffer_index: u32) {
+        let frame_buffer = self.frame_buffer.borrow().clone();
+        if frame_buffer.id() != frame_buffer_id {
+            return;
+        }
+        frame_buffer.release(buffer_index);
+        if let Some(rgba_image) = self.pending_frame.take() {
+            self.send_frame(rgba_image);
+        }
+    }
+
+    /// Writes the next frames to `frame_buffer`, which the widget sent for
+    /// a new size.
+    fn set_frame_buffer(&self, frame_buffer: FrameBuffer) {
+        self.frame_buffer.replace(Rc::new(frame_buffer));
+        // The widget drops the frames of the previous buffer, and the
+        // resize brings new ones
+        self.previous_frame.take();
+        self.pending_frame.take();
+    }
+
+    /// Presses and releases modifier keys until Servo has `modifiers`, the
+    /// ones of a pointer event. The keys may have changed while the view
+    /// didn't have the focus, and Servo would give the event stale ones.
+    fn sync_modifiers(&self, webview: &WebView, modifiers: u32) {
+        let modifiers = Modifiers::from_bits_truncate(modifiers);
+        let mut current = self.modifiers.get();
+        for (modifier, key, code, location) in MODIFIER_KEYS {
+            if current.contains(*modifier) == modifiers.contains(*modifier) {
+                continue;
+            }
+            let state = if modifiers.contains(*modifier) {
+                KeyState::Down
+            } else {
+                KeyState::Up
+            };
+            current.toggle(*modifier);
+            log::debug!("Synthesizing {key:?} {state:?} for a pointer event");
+            let key_event = KeyboardEvent::new_without_event(
+                state,
+                Key::Named(*key),
+                *code,
+                *location,
+                current,
+                false,
+                false,
+            );
+            webview.notify_input_event(InputEvent::Keyboard(key_event));
+        }
+        self.modifiers.set(current);
+    }
+
+    /// Ties the next page load to `load_id`, the widget's load that just
+    /// went to Servo.
+    fn begin_load(&self, load_id: u64) {
+        self.load_id.set(load_id);
+        self.load_started.set(false);
+    }
+
+    /// Tells the widget Servo didn't get to `load_id`.
+    fn fail_load(&self, load_id: u64, error: String) {
+        log::warn!("{error}");
+        let event = servo_event::Event::LoadFailed(LoadFailed { load_id, error });
+        let _ = send_event(self.webview_id, event);
+    }
+
+    /// Tells the widget about the page of a popup it just took over, which
+    /// started loading before it had a delegate.
+    fn notify_adopted(&self, webview: &WebView) {
+        if let Some(url) = webview.url() {
+            self.notify_url_changed(webview.clone(), url);
+        }
+        self.notify_page_title_changed(webview.clone(), webview.page_title());
+        self.notify_load_status_changed(webview.clone(), webview.load_status());
     }
 }
 
 impl WebViewDelegate for ServoWebViewDelegate {
     fn notify_new_frame_ready(&self, webview: WebView) {
+        // The document enlarged for a snapshot isn't meant for the widget
+        if let Some(document_size) = self.snapshots.document_size() {
+            if self.rendering_context.size() == document_size {
+                self.capture_document(&webview);
+            }
+            return;
+        }
+
         let size = self.rendering_context.size2d().to_i32();
         let viewport_rect = DeviceIntRect::from_origin_and_size(Point2D::origin(), size);
         webview.paint();
         self.rendering_context.present();
 
         if let Some(rgba_image) = self.rendering_context.read_to_image(viewport_rect) {
-            let width = rgba_image.width();
-            let height = rgba_image.height();
-            let data = rgba_image.into_raw();
-
-            let event = ServoEvent {
-                event: Some(servo_event::Event::FrameReady(FrameReady {
-                    rgba_data: data,
-                    width,
-                    height,
-                })),
-            };
-            let _ = send_event(event);
+            self.send_frame(rgba_image);
         }
     }
 
@@ -144,12 +484,121 @@ impl WebViewDelegate for ServoWebViewDelegate {
             servo::Cursor::Progress => "progress",
             _ => "default",
         };
-        let event = ServoEvent {
-            event: Some(servo_event::Event::CursorChanged(CursorChanged {
-                cursor: cursor_str.to_string(),
-            })),
+        let event = servo_event::Event::CursorChanged(CursorChanged {
+            cursor: cursor_str.to_string(),
+        });
+        let _ = send_event(self.webview_id, event);
+    }
+
+    fn notify_page_title_changed(&self, _webview: WebView, title: Option<String>) {
+        let event = servo_event::Event::TitleChanged(TitleChanged {
+            title: title.unwrap_or_default(),
+        });
+        let _ = send_event(self.webview_id, event);
+    }
+
+    fn notify_url_changed(&self, _webview: WebView, url: Url) {
+        let event = servo_event::Event::UrlChanged(UrlChanged {
+            url: url.to_string(),
+        });
+        let _ = send_event(self.webview_id, event);
+    }
+
+    fn notify_load_status_changed(&self, webview: WebView, status: LoadStatus) {
+        let url = webview.url().map(|url| url.to_string()).unwrap_or_default();
+        // Servo doesn't report any progress, so estimate it from the
+        // loading stages
+        let event = match status {
+            LoadStatus::Started => {
+                self.load_started.set(true);
+                servo_event::Event::LoadStart(LoadStart { url })
+            }
+            LoadStatus::HeadParsed => {
+                servo_event::Event::LoadProgress(LoadProgress { progress: 0.5 })
+            }
+            LoadStatus::Complete => {
+                // Only a load that started since it was asked for ends it,
+                // the page that was loading before may complete meanwhile
+                let load_id = if self.load_started.replace(false) {
+                    self.load_id.take()
+                } else {
+                    0
+                };
+                servo_event::Event::LoadEnd(LoadEnd { url, load_id })
+            }
         };
-        let _ = send_event(event);
+        let _ = send_event(self.webview_id, event);
+    }
+
+    fn request_navigation(&self, _webview: WebView, navigation_request: NavigationRequest) {
+        self.navigations.request(navigation_request);
+    }
+
+    fn show_simple_dialog(&self, _webview: WebView, dialog: SimpleDialog) {
+        self.dialogs.show(dialog);
+    }
+
+    fn request_unload(&self, _webview: WebView, unload_request: AllowOrDenyRequest) {
+        self.dialogs.confirm_unload(unload_request);
+    }
+
+    fn show_file_selection_dialog(
+        &self,
+        _webview: WebView,
+        filter_pattern: Vec<FilterPattern>,
+        allow_select_multiple: bool,
+        response_sender: IpcSender<Option<Vec<PathBuf>>>,
+    ) {
+        self.file_choosers
+            .request(filter_pattern, allow_select_multiple, move |paths| {
+                let _ = response_sender.send(paths);
+            });
+    }
+
+    fn show_ime(
+        &self,
+        _webview: WebView,
+        input_method_type: InputMethodType,
+        text: Option<(String, i32)>,
+        _multiline: bool,
+        position: DeviceIntRect,
+    ) {
+        if self.features & features::INPUT_METHODS != 0 {
+            self.ime.show(input_method_type, text, position);
+        }
+    }
+
+    fn hide_ime(&self, _webview: WebView) {
+        if self.features & features::INPUT_METHODS != 0 {
+            self.ime.hide();
+        }
+    }
+
+    fn show_context_menu(&self, webview: WebView, context_menu: ContextMenu) {
+        report_context_menu(self.webview_id, &webview, context_menu);
+    }
+
+    fn notify_history_changed(&self, _webview: WebView, entries: Vec<Url>, current: usize) {
+        let event = servo_event::Event::HistoryChanged(HistoryChanged {
+            can_go_back: current > 0,
+            can_go_forward: current + 1 < entries.len(),
+        });
+        let _ = send_event(self.webview_id, event);
+    }
+
+    fn request_open_auxiliary_webview(&self, _parent_webview: WebView) -> Option<WebView> {
+        if self.features & features::POPUPS == 0 {
+            return None;
+        }
+        self.popups.open(self.webview_id)
+    }
+
+    fn load_web_resource(&self, webview: WebView, load: WebResourceLoad) {
+        self.local_contents.intercept(&webview, load);
+    }
+
+    fn notify_closed(&self, _webview: WebView) {
+        let _ = send_event(self.webview_id, servo_event::Event::Closed(Closed {}));
     }
 }
 
@@ -159,8 +608,7 @@ fn init_crypto() {
         .expect("Error initializing crypto provider");
 }
 
-fn spawn_stdin_channel() -> Receiver<ServoAction> {
-    let (tx, rx) = mpsc::channel::<ServoAction>();
+fn spawn_stdin_channel(sender: Sender<RunnerMessage>) {
     thread::spawn(move || {
         let mut stdin = io::stdin();
         loop {
@@ -175,14 +623,25 @@ fn spawn_stdin_channel() -> Receiver<ServoAction> {
                 break;
             }
 
-            if let Ok(action) = ServoAction::decode_from_slice(&msg_buf)
-                && tx.send(action).is_err()
-            {
+            let action = match ServoAction::decode_from_slice(&msg_buf) {
+                Ok(action) => action,
+                Err(err) => {
+                    log::warn!("Failed to decode action from widget: {err}");
+                    continue;
+                }
+            };
+            if sender.send(RunnerMessage::Action(action)).is_err() {
                 break;
             }
         }
+
+        // The main loop blocks until woken up, so make sure it goes away
+        // together with the widget that owned it.
+        let _ = sender.send(RunnerMessage::Action(ServoAction {
+            webview_id: 0,
+            action: Some(servo_action::Action::Shutdown(true)),
+        }));
     });
-    rx
 }
 
 fn convert_location(proto_location: servo_gtk::proto_ipc::Location) -> Location {
@@ -194,14 +653,55 @@ fn convert_location(proto_location: servo_gtk::proto_ipc::Location) -> Location
     }
 }
 
-fn convert_key_event(
-    key_str: String,
+/// The parts of `KeyPress` and `KeyRelease` Servo needs.
+struct KeyFields {
+    key: String,
     key_type: i32,
     location: i32,
-    key_code: u32,
+    code: String,
     modifiers: u32,
-    state: KeyState,
-) -> KeyboardEvent {
+    repeat: bool,
+    is_composing: bool,
+}
+
+impl From<KeyPress> for KeyFields {
+    fn from(key_press: KeyPress) -> Self {
+        Self {
+            key: key_press.key,
+            key_type: key_press.key_type,
+            location: key_press.location,
+            code: key_press.code,
+            modifiers: key_press.modifiers,
+            repeat: key_press.repeat,
+            is_composing: key_press.is_composing,
+        }
+    }
+}
+
+impl From<KeyRelease> for KeyFields {
+    fn from(key_release: KeyRelease) -> Self {
+        Self {
+            key: key_release.key,
+            key_type: key_release.key_type,
+            location: key_release.location,
+            code: key_release.code,
+            modifiers: key_release.modifiers,
+            repeat: key_release.repeat,
+            is_composing: key_release.is_composing,
+        }
+    }
+}
+
+fn convert_key_event(fields: KeyFields, state: KeyState) -> KeyboardEvent {
+    let KeyFields {
+        key: key_str,
+        key_type,
+        location,
+        code,
+        modifiers,
+        repeat,
+        is_composing,
+    } = fields;
     let key = match servo_gtk::proto_ipc::KeyType::try_from(key_type)
         .unwrap_or(servo_gtk::proto_ipc::KeyType::Character)
     {
@@ -215,15 +715,574 @@ fn convert_key_event(
             .unwrap_or(servo_gtk::proto_ipc::Location::Standard),
     );
     let modifiers = Modifiers::from_bits_truncate(modifiers);
-    // TODO: Convert key_code to proper Code enum value
-    let _code = key_code; // Keep for future use
-    let code = Code::Unidentified;
-    KeyboardEvent::new_without_event(state, key, code, location, modifiers, false, false)
+    let code = Code::from_str(&code).unwrap_or(Code::Unidentified);
+    KeyboardEvent::new_without_event(state, key, code, location, modifiers, repeat, is_composing)
+}
+
+fn convert_js_value(value: JSValue) -> JsValue {
+    let value = match value {
+        JSValue::Undefined | JSValue::Null => js_value::Value::Null(true),
+        JSValue::Boolean(value) => js_value::Value::Boolean(value),
+        JSValue::Number(value) => js_value::Value::Number(value),
+        JSValue::String(value) => js_value::Value::String(value),
+        JSValue::Array(items) => js_value::Value::Array(JsArray {
+            items: items.into_iter().map(convert_js_value).collect(),
+        }),
+        JSValue::Object(properties) => js_value::Value::Object(JsObject {
+            properties: properties
+                .into_iter()
+                .map(|(name, value)| (name, convert_js_value(value)))
+                .collect(),
+        }),
+        // Elements, frames and windows are references into the page that
+        // can't be carried over to the widget
+        _ => js_value::Value::Null(true),
+    };
+    JsValue { value: Some(value) }
+}
+
+/// A web view created on behalf of one of the widgets.
+struct RunnerWebView {
+    webview: WebView,
+    delegate: Rc<ServoWebViewDelegate>,
+}
+
+/// Handles the actions meant for a single view.
+fn handle_view_action(view: &RunnerWebView, action_type: servo_action::Action) {
+    let RunnerWebView { webview, delegate } = view;
+    match action_type {
+        servo_action::Action::LoadUrl(load_url) => {
+            log::info!("Loading URL: {}", load_url.url);
+            match Url::parse(&load_url.url) {
+                Ok(parsed_url) => {
+                    delegate.local_contents.forget(webview);
+                    webview.load(parsed_url);
+                    delegate.begin_load(load_url.load_id);
+                }
+                Err(err) => delegate.fail_load(
+                    load_url.load_id,
+                    format!("Invalid URL {}: {err}", load_url.url),
+                ),
+            }
+        }
+        servo_action::Action::LoadHtml(load_html) => {
+            log::info!("Loading HTML with base URI: {}", load_html.base_uri);
+            match delegate.local_contents.load(
+                webview,
+                load_html.html.into_bytes(),
+                "text/html",
+                "UTF-8",
+                &load_html.base_uri,
+            ) {
+                Ok(()) => delegate.begin_load(load_html.load_id),
+                Err(err) => delegate.fail_load(load_html.load_id, err),
+            }
+        }
+        servo_action::Action::LoadBytes(load_bytes) => {
+            log::info!(
+                "Loading {} bytes of {} with base URI: {}",
+                load_bytes.data.len(),
+                load_bytes.mime_type,
+                load_bytes.base_uri
+            );
+            match delegate.local_contents.load(
+                webview,
+                load_bytes.data,
+                &load_bytes.mime_type,
+                &load_bytes.encoding,
+                &load_bytes.base_uri,
+            ) {
+                Ok(()) => delegate.begin_load(load_bytes.load_id),
+                Err(err) => delegate.fail_load(load_bytes.load_id, err),
+            }
+        }
+        servo_action::Action::Reload(_) => {
+            log::info!("Reloading page");
+            webview.reload();
+        }
+        servo_action::Action::GoBack(_) => {
+            log::info!("Going back");
+            let _ = webview.go_back(1);
+        }
+        servo_action::Action::GoForward(_) => {
+            log::info!("Going forward");
+            let _ = webview.go_forward(1);
+        }
+        servo_action::Action::Motion(motion) => {
+            log::debug!("Mouse motion: ({}, {})", motion.x, motion.y);
+            webview.notify_input_event(InputEvent::MouseMove(MouseMoveEvent::new(
+                WebViewPoint::Device(Point2D::new(motion.x as f32, motion.y as f32)),
+            )));
+        }
+        servo_action::Action::ButtonPress(button_press) => {
+            log::debug!(
+                "Button press: button {} at ({}, {})",
+                button_press.button,
+                button_press.x,
+                button_press.y
+            );
+            delegate.sync_modifiers(webview, button_press.modifiers);
+            delegate.navigations.note_modifiers(button_press.modifiers);
+            let mouse_button = match button_press.button {
+                1 => MouseButton::Left,
+                2 => MouseButton::Middle,
+                3 => MouseButton::Right,
+                _ => MouseButton::Left,
+            };
+            webview.notify_input_event(InputEvent::MouseButton(MouseButtonEvent::new(
+                MouseButtonAction::Down,
+                mouse_button,
+                WebViewPoint::Device(Point2D::new(button_press.x as f32, button_press.y as f32)),
+            )));
+        }
+        servo_action::Action::ButtonRelease(button_release) => {
+            log::debug!(
+                "Button release: button {} at ({}, {})",
+                button_release.button,
+                button_release.x,
+                button_release.y
+            );
+            delegate.navigations.note_click(button_release.button);
+            delegate.sync_modifiers(webview, button_release.modifiers);
+            delegate
+                .navigations
+                .note_modifiers(button_release.modifiers);
+            let mouse_button = match button_release.button {
+                1 => MouseButton::Left,
+                2 => MouseButton::Middle,
+                3 => MouseButton::Right,
+                _ => MouseButton::Left,
+            };
+            webview.notify_input_event(InputEvent::MouseButton(MouseButtonEvent::new(
+                MouseButtonAction::Up,
+                mouse_button,
+                WebViewPoint::Device(Point2D::new(
+                    button_release.x as f32,
+                    button_release.y as f32,
+                )),
+            )));
+        }
+        servo_action::Action::KeyPress(key_press) => {
+            log::debug!("Key press: {}", key_press.key);
+            delegate.navigations.note_modifiers(key_press.modifiers);
+            delegate
+                .modifiers
+                .set(Modifiers::from_bits_truncate(key_press.modifiers));
+            let key_event = convert_key_event(key_press.into(), KeyState::Down);
+            webview.notify_input_event(InputEvent::Keyboard(key_event));
+        }
+        servo_action::Action::KeyRelease(key_release) => {
+            log::debug!("Key release: {}", key_release.key);
+            delegate.navigations.note_modifiers(key_release.modifiers);
+            delegate
+                .modifiers
+                .set(Modifiers::from_bits_truncate(key_release.modifiers));
+            let key_event = convert_key_event(key_release.into(), KeyState::Up);
+            webview.notify_input_event(InputEvent::Keyboard(key_event));
+        }
+        servo_action::Action::TouchBegin(touch_begin) => {
+            log::debug!(
+                "Touch begin {} at ({}, {})",
+                touch_begin.id,
+                touch_begin.x,
+                touch_begin.y
+            );
+            webview.notify_input_event(InputEvent::Touch(servo::TouchEvent::new(
+                servo::TouchEventType::Down,
+                servo::TouchId(touch_begin.id as i32),
+                WebViewPoint::Device(Point2D::new(touch_begin.x as f32, touch_begin.y as f32)),
+            )));
+        }
+        servo_action::Action::TouchUpdate(touch_update) => {
+            log::debug!(
+                "Touch update {} at ({}, {})",
+                touch_update.id,
+                touch_update.x,
+                touch_update.y
+            );
+            webview.notify_input_event(InputEvent::Touch(servo::TouchEvent::new(
+                servo::TouchEventType::Move,
+                servo::TouchId(touch_update.id as i32),
+                WebViewPoint::Device(Point2D::new(touch_update.x as f32, touch_update.y as f32)),
+            )));
+        }
+        servo_action::Action::TouchEnd(touch_end) => {
+            log::debug!(
+                "Touch end {} at ({}, {})",
+                touch_end.id,
+                touch_end.x,
+                touch_end.y
+            );
+            webview.notify_input_event(InputEvent::Touch(servo::TouchEvent::new(
+                servo::TouchEventType::Up,
+                servo::TouchId(touch_end.id as i32),
+                WebViewPoint::Device(Point2D::new(touch_end.x as f32, touch_end.y as f32)),
+            )));
+        }
+        servo_action::Action::TouchCancel(touch_cancel) => {
+            log::debug!(
+                "Touch cancel {} at ({}, {})",
+                touch_cancel.id,
+                touch_cancel.x,
+                touch_cancel.y
+            );
+            webview.notify_input_event(InputEvent::Touch(servo::TouchEvent::new(
+                servo::TouchEventType::Cancel,
+                servo::TouchId(touch_cancel.id as i32),
+                WebViewPoint::Device(Point2D::new(touch_cancel.x as f32, touch_cancel.y as f32)),
+            )));
+        }
+        servo_action::Action::Scroll(scroll) => {
+            let unit = ScrollUnit::try_from(scroll.unit).unwrap_or(ScrollUnit::Wheel);
+            let phase = ScrollPhase::try_from(scroll.phase).unwrap_or(ScrollPhase::Discrete);
+            log::debug!(
+                "Scroll ({phase:?}): dx={}, dy={} {unit:?} at ({}, {})",
+                scroll.dx,
+                scroll.dy,
+                scroll.x,
+                scroll.y
+            );
+            delegate.sync_modifiers(webview, scroll.modifiers);
+            delegate.navigations.note_modifiers(scroll.modifiers);
+            // Shift turns vertical wheels sideways, as in other browsers
+            let (dx, dy) = if unit == ScrollUnit::Wheel
+                && Modifiers::from_bits_truncate(scroll.modifiers).contains(Modifiers::SHIFT)
+                && scroll.dx == 0.0
+            {
+                (scroll.dy, 0.0)
+            } else {
+                (scroll.dx, scroll.dy)
+            };
+            // Touchpads move the page along with the fingers
+            let step = match unit {
+                ScrollUnit::Wheel => WHEEL_CLICK_PIXELS,
+                ScrollUnit::Surface => 1.0,
+            };
+            // A gesture keeps scrolling what was under the pointer when it
+            // began, like in browsers, even once other content moved there
+            let (x, y) = match phase {
+                ScrollPhase::Begin => {
+                    delegate.scroll_origin.set(Some((scroll.x, scroll.y)));
+                    (scroll.x, scroll.y)
+                }
+                ScrollPhase::Update | ScrollPhase::End | ScrollPhase::Momentum => {
+                    delegate.scroll_origin.get().unwrap_or((scroll.x, scroll.y))
+                }
+                ScrollPhase::Discrete => {
+                    delegate.scroll_origin.set(None);
+                    (scroll.x, scroll.y)
+                }
+            };
+            // Gestures begin and end without moving anything
+            if dx != 0.0 || dy != 0.0 {
+                webview.notify_scroll_event(
+                    Scroll::Delta(WebViewVector::Device(DeviceVector2D::new(
+                        (step * dx) as f32,
+                        (step * dy) as f32,
+                    ))),
+                    WebViewPoint::Device(Point2D::new(x as f32, y as f32)),
+                );
+            }
+        }
+        servo_action::Action::ImePreedit(ime_preedit) => {
+            delegate.ime.preedit(webview, ime_preedit.text);
+        }
+        servo_action::Action::ImeCommit(ime_commit) => {
+            log::debug!("Committing composed text");
+            delegate.ime.commit(webview, ime_commit.text);
+        }
+        servo_action::Action::ImeDeleteSurrounding(ime_delete_surrounding) => {
+            delegate.ime.delete_surrounding(
+                webview,
+                ime_delete_surrounding.offset,
+                ime_delete_surrounding.length,
+            );
+        }
+        servo_action::Action::ReleaseFrame(release_frame) => {
+            delegate.release_frame(release_frame.frame_buffer_id, release_frame.buffer_index);
+        }
+        servo_action::Action::Snapshot(snapshot) => {
+            log::debug!("Taking snapshot {}", snapshot.request_id);
+            // The latest frame, whether the widget has it yet or not
+            let pending_frame = delegate.pending_frame.borrow();
+            let previous_frame = delegate.previous_frame.borrow();
+            delegate.snapshots.take(
+                webview,
+                pending_frame.as_ref().or(previous_frame.as_ref()),
+                snapshot,
+            );
+        }
+        servo_action::Action::EvaluateJavascript(evaluate_javascript) => {
+            log::debug!("Evaluating script {}", evaluate_javascript.request_id);
+            let webview_id = delegate.webview_id;
+            let request_id = evaluate_javascript.request_id;
+            webview.evaluate_javascript(evaluate_javascript.script, move |result| {
+                let result = match result {
+                    Ok(value) => java_script_result::Result::Value(convert_js_value(value)),
+                    Err(err) => java_script_result::Result::Error(format!("{err:?}")),
+                };
+                let event = servo_event::Event::JavascriptResult(JavaScriptResult {
+                    request_id,
+                    result: Some(result),
+                });
+                let _ = send_event(webview_id, event);
+            });
+        }
+        servo_action::Action::NavigationDecision(navigation_decision) => {
+            delegate
+                .navigations
+                .decide(navigation_decision.request_id, navigation_decision.allow);
+        }
+        servo_action::Action::DialogResponse(dialog_response) => {
+            delegate.dialogs.respond(
+                dialog_response.request_id,
+                dialog_response.confirmed,
+                dialog_response.text,
+            );
+        }
+        servo_action::Action::FileChooserResponse(file_chooser_response) => {
+            delegate.file_choosers.respond(
+                file_chooser_response.request_id,
+                file_chooser_response.paths,
+            );
+        }
+        servo_action::Action::StartDownload(start_download) => {
+            delegate
+                .downloads
+                .start(start_download.download_id, start_download.url);
+        }
+        servo_action::Action::DownloadDestination(download_destination) => {
+            delegate
+                .downloads
+                .set_destination(download_destination.download_id, download_destination.path);
+        }
+        servo_action::Action::CancelDownload(cancel_download) => {
+            delegate.downloads.cancel(cancel_download.download_id);
+        }
+        servo_action::Action::CreateWebView(_)
+        | servo_action::Action::CloseWebView(_)
+        | servo_action::Action::CreateResponse(_)
+        | servo_action::Action::Hello(_)
+        | servo_action::Action::RegisterUriScheme(_)
+        | servo_action::Action::SchemeResponse(_)
+        | servo_action::Action::Shutdown(_) => unreachable!("Handled by the runner"),
+    }
+}
+
+/// The web views of the process and what they share.
+struct Runner {
+    servo: Rc<Servo>,
+    views: HashMap<u64, RunnerWebView>,
+    frame_socket: OwnedFd,
+    scheme_requests: PendingSchemeRequests,
+    popups: Rc<PendingPopups>,
+    local_contents: LocalContents,
+    sender: Sender<RunnerMessage>,
+    timers: Rc<Timers>,
+    network: Rc<Network>,
+    /// The [`features`] the widget supports
+    features: u64,
+}
+
+impl Runner {
+    fn create_webview(&mut self, webview_id: u64) {
+        log::info!("Creating web view {webview_id}");
+        // The widget sends the view's frame buffer right before asking for it
+        let frame_buffer = match FrameBuffer::receive(self.frame_socket.as_fd(), 0) {
+            Ok(frame_buffer) => Rc::new(frame_buffer),
+            Err(err) => {
+                log::error!("Failed to receive the frame buffer of {webview_id}: {err}");
+                return;
+            }
+        };
+        let (width, height) = INITIAL_FRAME_SIZE;
+        let rendering_context = Rc::new(
+            SoftwareRenderingContext::new(PhysicalSize::new(width, height))
+                .expect("Failed to create Software rendering context"),
+        );
+        let delegate = Rc::new(ServoWebViewDelegate::new(
+            webview_id,
+            rendering_context.clone(),
+            frame_buffer,
+            self.sender.clone(),
+            self.timers.clone(),
+            self.popups.clone(),
+            self.local_contents.clone(),
+            self.network.clone(),
+            self.features,
+        ));
+        let webview = WebViewBuilder::new(&self.servo, rendering_context)
+            .delegate(delegate.clone())
+            .build();
+        self.scheme_requests.add_webview(webview.id(), webview_id);
+        self.views
+            .insert(webview_id, RunnerWebView { webview, delegate });
+    }
+
+    fn close_webview(&mut self, webview_id: u64) {
+        log::info!("Closing web view {webview_id}");
+        if let Some(view) = self.views.remove(&webview_id) {
+            self.scheme_requests.remove_webview(view.webview.id());
+            self.local_contents.forget(&view.webview);
+        }
+        self.popups.close_opened_by(webview_id);
+    }
+
+    /// Resizes the web view of `webview_id`, taking the frame buffer the
+    /// widget sent for the new size if there is one.
+    fn resize_webview(&self, webview_id: u64, resize: Resize) {
+        let Some(view) = self.views.get(&webview_id) else {
+            log::warn!("Can't resize unknown web view {webview_id}");
+            return;
+        };
+        let delegate = &view.delegate;
+        if delegate.frame_buffer.borrow().id() != resize.frame_buffer_id {
+            match FrameBuffer::receive(self.frame_socket.as_fd(), resize.frame_buffer_id) {
+                Ok(frame_buffer) => delegate.set_frame_buffer(frame_buffer),
+                Err(err) => log::error!(
+                    "Failed to receive the frame buffer of {webview_id} for {}x{}: {err}",
+                    resize.width,
+                    resize.height
+                ),
+            }
+        }
+
+        log::debug!("Resizing to {}x{}", resize.width, resize.height);
+        let size = PhysicalSize::new(resize.width, resize.height);
+        if !delegate.snapshots.defer_resize(size) {
+            view.webview.resize(size);
+        }
+    }
+
+    /// Replaces the web view of `webview_id` with the popup of `request_id`.
+    fn adopt_popup(&mut self, webview_id: u64, request_id: u64) {
+        let Some(popup) = self.popups.take(request_id) else {
+            return;
+        };
+        let Some(view) = self.views.get_mut(&webview_id) else {
+            log::warn!("Can't show popup {request_id} in unknown web view {webview_id}");
+            return;
+        };
+        log::info!("Showing popup {request_id} in web view {webview_id}");
+
+        let delegate = Rc::new(ServoWebViewDelegate::new(
+            webview_id,
+            popup.rendering_context,
+            view.delegate.frame_buffer.borrow().clone(),
+            self.sender.clone(),
+            self.timers.clone(),
+            self.popups.clone(),
+            self.local_contents.clone(),
+            self.network.clone(),
+            self.features,
+        ));
+        popup.webview.set_delegate(delegate.clone());
+        self.scheme_requests.remove_webview(view.webview.id());
+        self.scheme_requests
+            .add_webview(popup.webview.id(), webview_id);
+        self.local_contents.forget(&view.webview);
+        delegate.notify_adopted(&popup.webview);
+        *view = RunnerWebView {
+            webview: popup.webview,
+            delegate,
+        };
+    }
+
+    fn handle_action(&mut self, action: ServoAction) -> ControlFlow<()> {
+        let webview_id = action.webview_id;
+        let Some(action_type) = action.action else {
+            return ControlFlow::Continue(());
+        };
+
+        match action_type {
+            servo_action::Action::CreateWebView(_) => self.create_webview(webview_id),
+            servo_action::Action::Resize(resize) => self.resize_webview(webview_id, resize),
+            servo_action::Action::CloseWebView(_) => self.close_webview(webview_id),
+            servo_action::Action::CreateResponse(create_response) => {
+                if create_response.accepted {
+                    self.adopt_popup(webview_id, create_response.request_id);
+                } else {
+                    log::info!("Popup {} was blocked", create_response.request_id);
+                    // Dropping the popup closes it
+                    self.popups.take(create_response.request_id);
+                }
+            }
+            servo_action::Action::Hello(_) => log::warn!("The widget said hello twice"),
+            servo_action::Action::RegisterUriScheme(register_uri_scheme) => {
+                log::warn!(
+                    "URI scheme {} has to be registered before loading anything",
+                    register_uri_scheme.scheme
+                );
+            }
+            servo_action::Action::SchemeResponse(scheme_response) => {
+                log::debug!("Scheme response for request {}", scheme_response.request_id);
+                self.scheme_requests.complete(scheme_response);
+            }
+            servo_action::Action::Shutdown(_) => {
+                log::info!("Shutting down servo");
+                return ControlFlow::Break(());
+            }
+            action_type => match self.views.get(&webview_id) {
+                Some(view) => handle_view_action(view, action_type),
+                None => log::warn!("Got an action for unknown web view {webview_id}"),
+            },
+        }
+
+        ControlFlow::Continue(())
+    }
+}
+
+/// Waits for the widget to say hello, and checks it talks the same
+/// protocol. Returns the optional features both sides support, or `None`
+/// if the runner should exit.
+fn wait_for_hello(receiver: &Receiver<RunnerMessage>) -> Option<u64> {
+    let hello = loop {
+        match receiver.recv() {
+            Ok(RunnerMessage::Action(ServoAction {
+                action: Some(servo_action::Action::Hello(hello)),
+                ..
+            })) => break hello,
+            Ok(RunnerMessage::Action(ServoAction {
+                action: Some(servo_action::Action::Shutdown(_)),
+                ..
+            })) => return None,
+            Ok(RunnerMessage::Action(_)) => {
+                log::error!("The widget didn't say hello, it may be from an older version");
+                return None;
+            }
+            Ok(RunnerMessage::Log(log_message)) => send_log_message(log_message),
+            Ok(_) => {}
+            Err(_) => return None,
+        }
+    };
+
+    if hello.protocol_version != PROTOCOL_VERSION {
+        log::error!(
+            "The widget speaks version {} of the protocol instead of {PROTOCOL_VERSION}",
+            hello.protocol_version
+        );
+        return None;
+    }
+    if hello.engine_revision != ENGINE_REVISION {
+        log::debug!(
+            "The widget is built against Servo {} instead of {ENGINE_REVISION}",
+            hello.engine_revision
+        );
+    }
+    let missing_features = features::ALL & !hello.features;
+    if missing_features != 0 {
+        log::info!("The widget lacks features {missing_features:#x}, they are turned off");
+    }
+    Some(features::ALL & hello.features)
 }
 
 fn main() {
-    let (event_logger, log_receiver) = EventLogger::new();
+    // The widget checks this before anything else, logs included
+    let _ = send_event(0, servo_event::Event::Hello(Hello::current()));
+
+    let (sender, receiver) = mpsc::channel();
 
+    let event_logger = EventLogger::new(sender.clone());
     log::set_logger(Box::leak(Box::new(event_logger))).expect("Failed to set logger");
     log::set_max_level(log::LevelFilter::Debug);
 
@@ -232,195 +1291,152 @@ fn main() {
 
     log::info!("Starting servo runner");
 
-    let size = PhysicalSize::new(800, 600);
-    let rendering_context = Rc::new(
-        SoftwareRenderingContext::new(size).expect("Failed to create Software rendering context"),
-    );
+    let frame_socket = unsafe { OwnedFd::from_raw_fd(FRAME_SOCKET_FD) };
+
+    spawn_stdin_channel(sender.clone());
+    let Some(features) = wait_for_hello(&receiver) else {
+        return;
+    };
 
-    let servo_builder = ServoBuilder::default();
-    let servo = servo_builder.build();
+    // Custom schemes have to be known when building Servo, so collect the
+    // registrations the widget sends before anything else. Views created
+    // and resized meanwhile are set up once Servo exists.
+    let scheme_requests = PendingSchemeRequests::default();
+    let local_contents = LocalContents::default();
+    let mut protocol_registry = ProtocolRegistry::default();
+    if protocol_registry
+        .register(
+            LOCAL_CONTENT_SCHEME,
+            LocalContentHandler::new(local_contents.clone()),
+        )
+        .is_err()
+    {
+        log::warn!("Failed to register URI scheme {LOCAL_CONTENT_SCHEME}");
+    }
+    let mut deferred_actions = Vec::new();
+    let first_message = loop {
+        match receiver.recv() {
+            Ok(RunnerMessage::Action(ServoAction {
+                action: Some(servo_action::Action::RegisterUriScheme(register_uri_scheme)),
+                ..
+            })) => {
+                log::info!("Registering URI scheme {}", register_uri_scheme.scheme);
+                if protocol_registry
+                    .register(
+                        &register_uri_scheme.scheme,
+                        UriSchemeHandler::new(scheme_requests.clone()),
+                    )
+                    .is_err()
+                {
+                    log::warn!(
+                        "Failed to register URI scheme {}",
+                        register_uri_scheme.scheme
+                    );
+                }
+            }
+            Ok(RunnerMessage::Action(action)) if !action.starts_engine() => {
+                deferred_actions.push(action)
+            }
+            Ok(RunnerMessage::Log(log_message)) => send_log_message(log_message),
+            Ok(message) => break message,
+            Err(_) => return,
+        }
+    };
 
-    let delegate = Rc::new(ServoWebViewDelegate::new(rendering_context.clone()));
-    let webview = WebViewBuilder::new(&servo, rendering_context)
-        .delegate(delegate)
-        .build();
+    let servo_builder = ServoBuilder::default()
+        .event_loop_waker(Box::new(RunnerEventLoopWaker {
+            sender: sender.clone(),
+        }))
+        .protocol_registry(protocol_registry);
+    let servo = Rc::new(servo_builder.build());
 
-    let receiver = spawn_stdin_channel();
+    let network = Network::new(Rc::downgrade(&servo), sender.clone())
+        .expect("Failed to start the network thread");
 
-    loop {
-        // Process queued log messages
-        while let Ok(log_message) = log_receiver.try_recv() {
-            let event = ServoEvent {
-                event: Some(servo_event::Event::LogMessage(log_message)),
-            };
-            let _ = send_event(event);
-        }
+    let mut runner = Runner {
+        popups: Rc::new(PendingPopups::new(Rc::downgrade(&servo))),
+        servo,
+        views: HashMap::new(),
+        frame_socket,
+        scheme_requests,
+        local_contents,
+        sender,
+        timers: Rc::new(Timers::default()),
+        network: Rc::new(network),
+        features,
+    };
+    let mut pending_messages: Vec<RunnerMessage> = deferred_actions
+        .into_iter()
+        .map(RunnerMessage::Action)
+        .collect();
+    pending_messages.push(first_message);
+    let mut pending_messages = pending_messages.into_iter();
 
-        if let Ok(action) = receiver.try_recv()
-            && let Some(action_type) = action.action
-        {
-            match action_type {
-                servo_action::Action::LoadUrl(load_url) => {
-                    log::info!("Loading URL: {}", load_url.url);
-                    if let Ok(parsed_url) = Url::parse(&load_url.url) {
-                        webview.load(parsed_url);
+    // Block until there is something to do or a timer expires, then handle
+    // everything that got queued meanwhile so that bursts of input don't lag
+    // behind.
+    while let Some(message) = pending_messages
+        .next()
+        .or_else(|| runner.timers.recv(&receiver))
+    {
+        let mut spin = false;
+        let mut next = Some(message);
+        while let Some(message) = next {
+            match message {
+                RunnerMessage::Action(action) => {
+                    if runner.handle_action(action).is_break() {
+                        return;
                     }
+                    spin = true;
                 }
-                servo_action::Action::Reload(_) => {
-                    log::info!("Reloading page");
-                    webview.reload();
-                }
-                servo_action::Action::GoBack(_) => {
-                    log::info!("Going back");
-                    let _ = webview.go_back(1);
-                }
-                servo_action::Action::GoForward(_) => {
-                    log::info!("Going forward");
-                    let _ = webview.go_forward(1);
-                }
-                servo_action::Action::Resize(resize) => {
-                    log::debug!("Resizing to {}x{}", resize.width, resize.height);
-                    webview.resize(PhysicalSize::new(resize.width, resize.height));
-                }
-                servo_action::Action::Motion(motion) => {
-                    log::debug!("Mouse motion: ({}, {})", motion.x, motion.y);
-                    webview.notify_input_event(InputEvent::MouseMove(MouseMoveEvent::new(
-                        WebViewPoint::Device(Point2D::new(motion.x as f32, motion.y as f32)),
-                    )));
-                }
-                servo_action::Action::ButtonPress(button_press) => {
-                    log::debug!(
-                        "Button press: button {} at ({}, {})",
-                        button_press.button,
-                        button_press.x,
-                        button_press.y
-                    );
-                    let mouse_button = match button_press.button {
-                        1 => MouseButton::Left,
-                        2 => MouseButton::Middle,
-                        3 => MouseButton::Right,
-                        _ => MouseButton::Left,
-                    };
-                    webview.notify_input_event(InputEvent::MouseButton(MouseButtonEvent::new(
-                        MouseButtonAction::Down,
-                        mouse_button,
-                        WebViewPoint::Device(Point2D::new(
-                            button_press.x as f32,
-                            button_press.y as f32,
-                        )),
-                    )));
-                }
-                servo_action::Action::ButtonRelease(button_release) => {
-                    log::debug!(
-                        "Button release: button {} at ({}, {})",
-                        button_release.button,
-                        button_release.x,
-                        button_release.y
-                    );
-                    let mouse_button = match button_release.button {
-                        1 => MouseButton::Left,
-                        2 => MouseButton::Middle,
-                        3 => MouseButton::Right,
-                        _ => MouseButton::Left,
-                    };
-                    webview.notify_input_event(InputEvent::MouseButton(MouseButtonEvent::new(
-                        MouseButtonAction::Up,
-                        mouse_button,
-                        WebViewPoint::Device(Point2D::new(
-                            button_release.x as f32,
-                            button_release.y as f32,
-                        )),
-                    )));
-                }
-                servo_action::Action::KeyPress(key_press) => {
-                    log::debug!("Key press: {}", key_press.key);
-                    let key_event = convert_key_event(
-                        key_press.key,
-                        key_press.key_type,
-                        key_press.location,
-                        key_press.key_code,
-                        key_press.modifiers,
-                        KeyState::Down,
-                    );
-                    webview.notify_input_event(InputEvent::Keyboard(key_event));
-                }
-                servo_action::Action::KeyRelease(key_release) => {
-                    log::debug!("Key release: {}", key_release.key);
-                    let key_event = convert_key_event(
-                        key_release.key,
-                        key_release.key_type,
-                        key_release.location,
-                        key_release.key_code,
-                        key_release.modifiers,
-                        KeyState::Up,
-                    );
-                    webview.notify_input_event(InputEvent::Keyboard(key_event));
-                }
-                servo_action::Action::TouchBegin(touch_begin) => {
-                    log::debug!("Touch begin at ({}, {})", touch_begin.x, touch_begin.y);
-                    webview.notify_input_event(InputEvent::Touch(servo::TouchEvent::new(
-                        servo::TouchEventType::Down,
-                        servo::TouchId(0),
-                        WebViewPoint::Device(Point2D::new(
-                            touch_begin.x as f32,
-                            touch_begin.y as f32,
-                        )),
-                    )));
-                }
-                servo_action::Action::TouchUpdate(touch_update) => {
-                    log::debug!("Touch update at ({}, {})", touch_update.x, touch_update.y);
-                    webview.notify_input_event(InputEvent::Touch(servo::TouchEvent::new(
-                        servo::TouchEventType::Move,
-                        servo::TouchId(0),
-                        WebViewPoint::Device(Point2D::new(
-                            touch_update.x as f32,
-                            touch_update.y as f32,
-                        )),
-                    )));
-                }
-                servo_action::Action::TouchEnd(touch_end) => {
-                    log::debug!("Touch end at ({}, {})", touch_end.x, touch_end.y);
-                    webview.notify_input_event(InputEvent::Touch(servo::TouchEvent::new(
-                        servo::TouchEventType::Up,
-                        servo::TouchId(0),
-                        WebViewPoint::Device(Point2D::new(touch_end.x as f32, touch_end.y as f32)),
-                    )));
+                RunnerMessage::Log(log_message) => send_log_message(log_message),
+                RunnerMessage::NavigationTimeout {
+                    webview_id,
+                    request_id,
+                } => {
+                    if let Some(view) = runner.views.get(&webview_id) {
+                        view.delegate.navigations.decide(request_id, true);
+                    }
+                    spin = true;
                 }
-                servo_action::Action::TouchCancel(touch_cancel) => {
-                    log::debug!("Touch cancel at ({}, {})", touch_cancel.x, touch_cancel.y);
-                    webview.notify_input_event(InputEvent::Touch(servo::TouchEvent::new(
-                        servo::TouchEventType::Cancel,
-                        servo::TouchId(0),
-                        WebViewPoint::Device(Point2D::new(
-                            touch_cancel.x as f32,
-                            touch_cancel.y as f32,
-                        )),
-                    )));
+                RunnerMessage::Download(webview_id, download_message) => {
+                    if let Some(view) = runner.views.get(&webview_id) {
+                        view.delegate.downloads.handle_message(download_message);
+                    }
+                    spin = true;
                 }
-                servo_action::Action::Scroll(scroll) => {
-                    log::debug!("Scroll: dx={}, dy={}", scroll.dx, scroll.dy);
-                    // FIXME: 20 and 10 are random numbers that appear in
-                    // winit_minimal. We should properly understand it and
-                    // maybe add some constants
-                    webview.notify_scroll_event(
-                        Scroll::Delta(WebViewVector::Device(DeviceVector2D::new(
-                            20.0 * scroll.dx as f32,
-                            20.0 * scroll.dy as f32,
-                        ))),
-                        WebViewPoint::Device(Point2D::new(10.0, 10.0)),
-                    );
+                RunnerMessage::DocumentSize {
+                    webview_id,
+                    capture_id,
+                    result,
+                } => {
+                    if let Some(view) = runner.views.get(&webview_id) {
+                        view.delegate
+                            .document_measured(&view.webview, capture_id, result);
+                    }
+                    spin = true;
                 }
-                servo_action::Action::Shutdown(_) => {
-                    log::info!("Shutting down servo");
-                    break;
+                RunnerMessage::DocumentTimeout {
+                    webview_id,
+                    capture_id,
+                } => {
+                    if let Some(view) = runner.views.get(&webview_id) {
+                        view.delegate.snapshots.timed_out(&view.webview, capture_id);
+                    }
+                    spin = true;
                 }
+                RunnerMessage::Wake => spin = true,
             }
+            next = pending_messages
+                .next()
+                .or_else(|| receiver.try_recv().ok())
+                .or_else(|| runner.timers.take_expired());
         }
 
-        // Spin servo event loop
-        servo.spin_event_loop();
-
-        // FIXME: we need a better way to not have a busy loop
-        std::thread::sleep(Duration::from_millis(5));
+        // Log messages alone don't need servo to make progress, and spinning
+        // for them would wake us up again with the logs of the spin itself.
+        if spin {
+            runner.servo.spin_event_loop();
+        }
     }
 }
@@ -0,0 +1,321 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::{Cell, RefCell};
+use std::io::Cursor;
+use std::rc::Rc;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use dpi::PhysicalSize;
+use embedder_traits::JSValue;
+use image::imageops::{self, FilterType};
+use image::{ImageFormat, RgbaImage};
+use servo::WebView;
+use servo_gtk::proto_ipc::{Snapshot, SnapshotFormat, SnapshotRegion, SnapshotResult, servo_event};
+
+use crate::timer::Timers;
+use crate::{RunnerMessage, send_event};
+
+/// The largest side of a full-document snapshot, which is rendered in one
+/// go.
+const MAX_DOCUMENT_SIZE: u32 = 16384;
+
+/// How long measuring and rendering the document may take before the
+/// full-document snapshots fail.
+const DOCUMENT_CAPTURE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const DOCUMENT_SIZE_SCRIPT: &str = r#"(function () {
+    const root = document.documentElement;
+    const body = document.body;
+    return [
+        Math.max(root.scrollWidth, body ? body.scrollWidth : 0),
+        Math.max(root.scrollHeight, body ? body.scrollHeight : 0),
+    ];
+})()"#;
+
+/// A captured image as sent to the widget.
+struct Encoded {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+fn encode(image: &RgbaImage, scale: f64, format: SnapshotFormat) -> Result<Encoded, String> {
+    let resized;
+    let image = if scale == 1.0 {
+        image
+    } else {
+        let width = ((image.width() as f64 * scale).round() as u32).max(1);
+        let height = ((image.height() as f64 * scale).round() as u32).max(1);
+        resized = imageops::resize(image, width, height, FilterType::Triangle);
+        &resized
+    };
+
+    let data = match format {
+        SnapshotFormat::Png => {
+            let mut png = Vec::new();
+            image
+                .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+                .map_err(|err| format!("Failed to encode the snapshot: {err}"))?;
+            png
+        }
+        SnapshotFormat::Rgba => image.as_raw().clone(),
+    };
+    Ok(Encoded {
+        data,
+        width: image.width(),
+        height: image.height(),
+    })
+}
+
+fn crop(frame: &RgbaImage, x: i32, y: i32, width: i32, height: i32) -> Result<RgbaImage, String> {
+    let left = x.clamp(0, frame.width() as i32);
+    let top = y.clamp(0, frame.height() as i32);
+    let right = x.saturating_add(width).clamp(0, frame.width() as i32);
+    let bottom = y.saturating_add(height).clamp(0, frame.height() as i32);
+    if right <= left || bottom <= top {
+        return Err("The rectangle is outside of the view".to_string());
+    }
+
+    Ok(imageops::crop_imm(
+        frame,
+        left as u32,
+        top as u32,
+        (right - left) as u32,
+        (bottom - top) as u32,
+    )
+    .to_image())
+}
+
+#[derive(Clone, Copy)]
+struct DocumentCapture {
+    size: PhysicalSize<u32>,
+    /// The size to go back to once the document is captured
+    restore_size: PhysicalSize<u32>,
+}
+
+/// Snapshots requested by the widget.
+///
+/// The visible part of the page is cropped from the last frame. The full
+/// document is captured by enlarging the view to the size of the document
+/// until the next frame is rendered.
+pub(crate) struct Snapshots {
+    webview_id: u64,
+    sender: Sender<RunnerMessage>,
+    timers: Rc<Timers>,
+    /// Full-document snapshots with their scale and format, which all share
+    /// the next capture
+    document_requests: RefCell<Vec<(u64, f64, SnapshotFormat)>>,
+    /// Tells the measurements and timeouts of the current capture from
+    /// those of earlier ones
+    capture_id: Cell<u64>,
+    capture: Cell<Option<DocumentCapture>>,
+}
+
+impl Snapshots {
+    pub(crate) fn new(webview_id: u64, sender: Sender<RunnerMessage>, timers: Rc<Timers>) -> Self {
+        Self {
+            webview_id,
+            sender,
+            timers,
+            document_requests: RefCell::new(Vec::new()),
+            capture_id: Cell::new(0),
+            capture: Cell::new(None),
+        }
+    }
+
+    fn send_result(&self, request_id: u64, result: Result<Encoded, String>) {
+        let event = servo_event::Event::SnapshotResult(match result {
+            Ok(Encoded {
+                data,
+                width,
+                height,
+            }) => SnapshotResult {
+                request_id,
+                data,
+                width,
+                height,
+                ..Default::default()
+            },
+            Err(error) => SnapshotResult {
+                request_id,
+                error,
+                ..Default::default()
+            },
+        });
+        let _ = send_event(self.webview_id, event);
+    }
+
+    /// Captures what `snapshot` asks for, `frame` being what the widget
+    /// currently shows.
+    pub(crate) fn take(&self, webview: &WebView, frame: Option<&RgbaImage>, snapshot: Snapshot) {
+        let region = SnapshotRegion::try_from(snapshot.region).unwrap_or(SnapshotRegion::Visible);
+        let format = SnapshotFormat::try_from(snapshot.format).unwrap_or(SnapshotFormat::Png);
+        let frame = frame.ok_or_else(|| "Nothing has been rendered yet".to_string());
+        let result = match region {
+            SnapshotRegion::FullDocument => {
+                self.request_document(webview, snapshot.request_id, snapshot.scale, format);
+                return;
+            }
+            SnapshotRegion::Visible => {
+                frame.and_then(|frame| encode(frame, snapshot.scale, format))
+            }
+            SnapshotRegion::Rectangle => frame
+                .and_then(|frame| {
+                    crop(
+                        frame,
+                        snapshot.x,
+                        snapshot.y,
+                        snapshot.width,
+                        snapshot.height,
+                    )
+                })
+                .and_then(|image| encode(&image, snapshot.scale, format)),
+        };
+        self.send_result(snapshot.request_id, result);
+    }
+
+    fn request_document(
+        &self,
+        webview: &WebView,
+        request_id: u64,
+        scale: f64,
+        format: SnapshotFormat,
+    ) {
+        let mut document_requests = self.document_requests.borrow_mut();
+        document_requests.push((request_id, scale, format));
+        if document_requests.len() > 1 {
+            return;
+        }
+
+        let webview_id = self.webview_id;
+        let capture_id = self.capture_id.get() + 1;
+        self.capture_id.set(capture_id);
+        self.timers.schedule(
+            DOCUMENT_CAPTURE_TIMEOUT,
+            RunnerMessage::DocumentTimeout {
+                webview_id,
+                capture_id,
+            },
+        );
+
+        let sender = self.sender.clone();
+        webview.evaluate_javascript(DOCUMENT_SIZE_SCRIPT, move |result| {
+            let result = result.map_err(|err| format!("{err:?}"));
+            let _ = sender.send(RunnerMessage::DocumentSize {
+                webview_id,
+                capture_id,
+                result,
+            });
+        });
+    }
+
+    fn fail_document(&self, error: String) {
+        log::warn!("{error}");
+        for (request_id, _, _) in self.document_requests.take() {
+            self.send_result(request_id, Err(error.clone()));
+        }
+    }
+
+    /// Enlarges the view to the document measured by `result`. Returns
+    /// whether the view already has that size, in which case nothing is
+    /// going to be rendered and the document has to be captured right away.
+    pub(crate) fn document_measured(
+        &self,
+        webview: &WebView,
+        capture_id: u64,
+        current_size: PhysicalSize<u32>,
+        result: Result<JSValue, String>,
+    ) -> bool {
+        if capture_id != self.capture_id.get() || self.document_requests.borrow().is_empty() {
+            // Timed out meanwhile
+            return false;
+        }
+        let size = match result {
+            Ok(JSValue::Array(values)) => match values.as_slice() {
+                [JSValue::Number(width), JSValue::Number(height)] => PhysicalSize::new(
+                    (*width as u32).clamp(1, MAX_DOCUMENT_SIZE),
+                    (*height as u32).clamp(1, MAX_DOCUMENT_SIZE),
+                ),
+                _ => {
+                    self.fail_document("Failed to measure the document".to_string());
+                    return false;
+                }
+            },
+            Ok(_) => {
+                self.fail_document("Failed to measure the document".to_string());
+                return false;
+            }
+            Err(err) => {
+                self.fail_document(format!("Failed to measure the document: {err}"));
+                return false;
+            }
+        };
+
+        log::debug!("Capturing the document at {}x{}", size.width, size.height);
+        self.capture.set(Some(DocumentCapture {
+            size,
+            restore_size: current_size,
+        }));
+        if size == current_size {
+            return true;
+        }
+        webview.resize(size);
+        false
+    }
+
+    /// Fails the full-document snapshots of `capture_id` if they are still
+    /// waiting, and gives the view its size back.
+    pub(crate) fn timed_out(&self, webview: &WebView, capture_id: u64) {
+        if capture_id != self.capture_id.get() || self.document_requests.borrow().is_empty() {
+            return;
+        }
+        if let Some(capture) = self.capture.take()
+            && capture.restore_size != capture.size
+        {
+            webview.resize(capture.restore_size);
+        }
+        self.fail_document("Timed out capturing the document".to_string());
+    }
+
+    /// The size the document is being rendered at for a capture.
+    pub(crate) fn document_size(&self) -> Option<PhysicalSize<u32>> {
+        self.capture.get().map(|capture| capture.size)
+    }
+
+    /// Keeps the widget's size for after the capture in progress, if any.
+    /// Returns whether there was one.
+    pub(crate) fn defer_resize(&self, size: PhysicalSize<u32>) -> bool {
+        match self.capture.get() {
+            Some(capture) => {
+                self.capture.set(Some(DocumentCapture {
+                    restore_size: size,
+                    ..capture
+                }));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Answers the full-document snapshots with `image`, the rendered
+    /// document, and gives the view its size back.
+    pub(crate) fn finish_document(&self, webview: &WebView, image: Option<RgbaImage>) {
+        let Some(capture) = self.capture.take() else {
+            return;
+        };
+        if capture.restore_size != capture.size {
+            webview.resize(capture.restore_size);
+        }
+
+        for (request_id, scale, format) in self.document_requests.take() {
+            let result = image
+                .as_ref()
+                .ok_or_else(|| "Failed to read the rendered document".to_string())
+                .and_then(|image| encode(image, scale, format));
+            self.send_result(request_id, result);
+        }
+    }
+}
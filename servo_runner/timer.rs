@@ -0,0 +1,69 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::cell::RefCell;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::RunnerMessage;
+
+/// Messages the main loop hands to itself once their deadline passes.
+///
+/// The main loop waits for the channel until the nearest deadline, so
+/// timeouts don't need threads of their own.
+#[derive(Default)]
+pub(crate) struct Timers {
+    timers: RefCell<Vec<(Instant, RunnerMessage)>>,
+}
+
+impl Timers {
+    /// Queues `message` for when `delay` has passed.
+    pub(crate) fn schedule(&self, delay: Duration, message: RunnerMessage) {
+        self.timers
+            .borrow_mut()
+            .push((Instant::now() + delay, message));
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.timers
+            .borrow()
+            .iter()
+            .map(|(deadline, _)| *deadline)
+            .min()
+    }
+
+    /// Takes the message whose deadline passed first, if any did.
+    pub(crate) fn take_expired(&self) -> Option<RunnerMessage> {
+        let now = Instant::now();
+        let mut timers = self.timers.borrow_mut();
+        let index = timers
+            .iter()
+            .enumerate()
+            .filter(|(_, (deadline, _))| *deadline <= now)
+            .min_by_key(|(_, (deadline, _))| *deadline)
+            .map(|(index, _)| index)?;
+        Some(timers.swap_remove(index).1)
+    }
+
+    /// Blocks until a message arrives on `receiver` or a deadline passes.
+    /// Returns `None` once the channel is gone.
+    pub(crate) fn recv(&self, receiver: &Receiver<RunnerMessage>) -> Option<RunnerMessage> {
+        loop {
+            if let Some(message) = self.take_expired() {
+                return Some(message);
+            }
+            let result = match self.next_deadline() {
+                Some(deadline) => {
+                    receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match result {
+                Ok(message) => return Some(message),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::channel::oneshot;
+use http::header::{CONTENT_TYPE, HeaderValue};
+use net::fetch::methods::{DoneChannel, FetchContext};
+use net::protocols::ProtocolHandler;
+use net_traits::http_status::HttpStatus;
+use net_traits::request::Request;
+use net_traits::response::{Response, ResponseBody};
+use net_traits::{NetworkError, ResourceFetchTiming};
+
+use servo::WebViewId;
+use servo_gtk::proto_ipc::{SchemeRequest, SchemeResponse, servo_event};
+
+use crate::send_event;
+
+/// Custom scheme requests waiting for a reply from the widget.
+#[derive(Clone, Default)]
+pub(crate) struct PendingSchemeRequests {
+    next_request_id: Arc<AtomicU64>,
+    senders: Arc<Mutex<HashMap<u64, oneshot::Sender<SchemeResponse>>>>,
+    // Requests are made from the network threads, which only know Servo's
+    // ids for the views
+    webview_ids: Arc<Mutex<HashMap<WebViewId, u64>>>,
+}
+
+impl PendingSchemeRequests {
+    fn add(&self) -> (u64, oneshot::Receiver<SchemeResponse>) {
+        let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.senders.lock().unwrap().insert(request_id, sender);
+        (request_id, receiver)
+    }
+
+    pub(crate) fn add_webview(&self, servo_webview_id: WebViewId, webview_id: u64) {
+        self.webview_ids
+            .lock()
+            .unwrap()
+            .insert(servo_webview_id, webview_id);
+    }
+
+    pub(crate) fn remove_webview(&self, servo_webview_id: WebViewId) {
+        self.webview_ids.lock().unwrap().remove(&servo_webview_id);
+    }
+
+    fn webview_id(&self, servo_webview_id: Option<WebViewId>) -> u64 {
+        servo_webview_id
+            .and_then(|servo_webview_id| {
+                self.webview_ids
+                    .lock()
+                    .unwrap()
+                    .get(&servo_webview_id)
+                    .copied()
+            })
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn complete(&self, response: SchemeResponse) {
+        match self.senders.lock().unwrap().remove(&response.request_id) {
+            Some(sender) => {
+                let _ = sender.send(response);
+            }
+            None => log::warn!("Got a response for unknown request {}", response.request_id),
+        }
+    }
+}
+
+/// Serves a custom scheme by forwarding its requests to the widget.
+pub(crate) struct UriSchemeHandler {
+    pending: PendingSchemeRequests,
+}
+
+impl UriSchemeHandler {
+    pub(crate) fn new(pending: PendingSchemeRequests) -> Self {
+        Self { pending }
+    }
+}
+
+impl ProtocolHandler for UriSchemeHandler {
+    fn load(
+        &self,
+        request: &mut Request,
+        _done_chan: &mut DoneChannel,
+        _context: &FetchContext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send>> {
+        let url = request.current_url();
+        let timing = ResourceFetchTiming::new(request.timing_type());
+
+        let (request_id, receiver) = self.pending.add();
+        let webview_id = self.pending.webview_id(request.target_webview_id);
+        let event = servo_event::Event::SchemeRequest(SchemeRequest {
+            request_id,
+            uri: url.to_string(),
+            method: request.method.to_string(),
+        });
+        let _ = send_event(webview_id, event);
+
+        Box::pin(async move {
+            let scheme_response = match receiver.await {
+                Ok(scheme_response) if scheme_response.error.is_empty() => scheme_response,
+                Ok(scheme_response) => {
+                    return Response::network_error(NetworkError::Internal(scheme_response.error));
+                }
+                Err(_) => {
+                    return Response::network_error(NetworkError::Internal(
+                        "The request was cancelled".to_string(),
+                    ));
+                }
+            };
+
+            let mut response = Response::new(url, timing);
+            response.status = HttpStatus::new_raw(scheme_response.status as u16, vec![]);
+            if let Ok(mime_type) = HeaderValue::from_str(&scheme_response.mime_type) {
+                response.headers.insert(CONTENT_TYPE, mime_type);
+            }
+            *response.body.lock().unwrap() = ResponseBody::Done(scheme_response.data);
+            response
+        })
+    }
+}